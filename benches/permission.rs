@@ -0,0 +1,59 @@
+//! Compares indexed permission checks against walking the ancestors, on trees
+//! built by `make_user_tree_test`.
+//!
+//! Run with `cargo bench --bench permission`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use tree_structured_org::{Database, Event, SecretKey, UserTree};
+
+/// Times one pass of check over every user, returning the mean time per check.
+fn time_checks(tree: &UserTree, user_ids: &[String], check: fn(&UserTree, &str) -> bool) -> Duration {
+    let start = Instant::now();
+
+    for user_id in user_ids {
+        black_box(check(tree, user_id));
+    }
+
+    start.elapsed() / user_ids.len() as u32
+}
+
+fn main() {
+    let key = SecretKey::generate();
+
+    for (branch, level) in [(5, 7), (3, 13), (2, 500)] {
+        let certificate = format!("bench-{branch}-{level}");
+        let database = Database::new();
+        let event = Event::AddUserTree {
+            certificate: certificate.clone(),
+            admin_key: key.public_key(),
+        };
+
+        database
+            .add_user_tree(&certificate, key.public_key(), database.authorize(&event, &key))
+            .expect("bench tree is created");
+        database.make_user_tree_test(branch, level, &certificate, &key);
+
+        let tree = database
+            .read_tree(&certificate, UserTree::clone)
+            .expect("bench tree exists");
+        let user_ids: Vec<String> = tree.users().map(|user| user.id.clone()).collect();
+
+        for user_id in user_ids.iter() {
+            assert_eq!(tree.check_user_permission(user_id), tree.walk_user_permission(user_id));
+        }
+
+        let indexed = time_checks(&tree, &user_ids, |tree, user_id| {
+            tree.check_user_permission(user_id).is_ok()
+        });
+        let walk = time_checks(&tree, &user_ids, |tree, user_id| {
+            tree.walk_user_permission(user_id).is_ok()
+        });
+
+        println!(
+            "branch {branch}, level {level}: {} users, indexed {indexed:?}/check, walk {walk:?}/check",
+            user_ids.len()
+        );
+    }
+}
@@ -0,0 +1,220 @@
+use tree_structured_org::{BlockReason, Database, Event, Limits, ReportReason, Result, Role, SecretKey};
+
+fn add_user_tree(database: &Database, certificate: &str, key: &SecretKey) -> Result<()> {
+    let event = Event::AddUserTree {
+        certificate: certificate.to_owned(),
+        admin_key: key.public_key(),
+    };
+
+    database.add_user_tree(certificate, key.public_key(), database.authorize(&event, key))
+}
+
+fn add_user(database: &Database, user_id: &str, parent: &str, certificate: &str, key: &SecretKey) -> Result<()> {
+    let event = Event::AddUser {
+        certificate: certificate.to_owned(),
+        user_id: user_id.to_owned(),
+        parent: parent.to_owned(),
+        role: Role::Moderator,
+        limits: Limits::NONE,
+        public_key: key.public_key(),
+    };
+
+    database.add_user(
+        user_id,
+        parent,
+        Role::Moderator,
+        Limits::NONE,
+        key.public_key(),
+        certificate,
+        database.authorize(&event, key),
+    )
+}
+
+fn report_user(
+    database: &Database,
+    user_id: &str,
+    reporter: &str,
+    reason: ReportReason,
+    certificate: &str,
+    key: &SecretKey,
+) -> Result<()> {
+    let event = Event::ReportUser {
+        certificate: certificate.to_owned(),
+        user_id: user_id.to_owned(),
+        reporter: reporter.to_owned(),
+        reason,
+        text: None,
+    };
+
+    database.report_user(
+        user_id,
+        reporter,
+        reason,
+        None,
+        certificate,
+        database.authorize(&event, key),
+    )
+}
+
+fn block_user(database: &Database, user_id: &str, blocker: &str, certificate: &str, key: &SecretKey) -> Result<()> {
+    let event = Event::BlockUser {
+        certificate: certificate.to_owned(),
+        user_id: user_id.to_owned(),
+        blocker: blocker.to_owned(),
+        duration: None,
+        reason: BlockReason::Other,
+        text: None,
+    };
+
+    database.block_user(
+        user_id,
+        blocker,
+        None,
+        BlockReason::Other,
+        None,
+        certificate,
+        database.authorize(&event, key),
+    )
+}
+
+fn unblock_user(database: &Database, user_id: &str, unblocker: &str, certificate: &str, key: &SecretKey) -> Result<()> {
+    let event = database.unblock_event(user_id, unblocker, BlockReason::Resolved, None, certificate);
+    database.unblock_user(
+        user_id,
+        unblocker,
+        BlockReason::Resolved,
+        None,
+        certificate,
+        database.authorize(&event, key),
+    )
+}
+
+fn print_permission(database: &Database, user_id: &str, certificate: &str) {
+    match database.check_user_permission(user_id, certificate) {
+        Ok(permission) => {
+            if permission.is_granted() {
+                println!(
+                    "user {user_id} has permission with capabilities {}.",
+                    permission.capabilities
+                )
+            } else {
+                println!("user {user_id} does not have permission.")
+            }
+        }
+        Err(error) => println!("{}", error.full_message()),
+    }
+}
+
+/// Walks through the tree operations on an in-memory database.
+fn main() {
+    let database = Database::new();
+    // Every user of the demo trees holds the same key
+    let key = SecretKey::generate();
+
+    for certificate in ["post", "comment", "view"] {
+        match add_user_tree(&database, certificate, &key) {
+            Ok(()) => println!("Added user tree under certificate '{certificate}'"),
+            Err(error) => println!("{}", error.full_message()),
+        }
+    }
+
+    // Unsuccessful (user tree already exists)
+    if let Err(error) = add_user_tree(&database, "view", &key) {
+        println!("{}", error.full_message())
+    }
+
+    // Unsuccessful (certificate does not exists)
+    if let Err(error) = database.check_user_permission("hassan", "jack") {
+        println!("{}", error.full_message())
+    }
+
+    // Unsuccessful (identical parent and user ids)
+    if let Err(error) = add_user(&database, "hassan", "hassan", "post", &key) {
+        println!("{}", error.full_message())
+    }
+
+    // Unsuccessful (parent 'hassan' not found)
+    if let Err(error) = add_user(&database, "hassan-1", "hassan", "post", &key) {
+        println!("{}", error.full_message())
+    }
+
+    let certificate = "post";
+
+    database.make_user_tree_test(6, 6, certificate, &key);
+
+    // Unsuccessful (signed by a key the parent does not hold)
+    if let Err(error) = add_user(&database, "admin-2-6", "admin-2", certificate, &SecretKey::generate()) {
+        println!("{}", error.full_message())
+    }
+
+    database.print_user_info("admin", certificate);
+    database.print_user_info("admin-2", certificate);
+    database.print_user_info("admin-2-1", certificate);
+
+    // Unsuccessful (user 'admin-2-1' already exists)
+    if let Err(error) = add_user(&database, "admin-2-1", "admin-2", certificate, &key) {
+        println!("{}", error.full_message())
+    }
+
+    // The second report by admin-3 replaces its first instead of counting twice
+    for (user_id, reporter) in [
+        ("admin-2-1", "admin-3"),
+        ("admin-2-1", "admin-3"),
+        ("admin-2-1", "admin-4"),
+        ("admin-2-1-3", "admin-3"),
+    ] {
+        if let Err(error) = report_user(&database, user_id, reporter, ReportReason::Spam, certificate, &key) {
+            println!("{}", error.full_message())
+        }
+    }
+
+    database.print_user_info("admin-2-1", certificate);
+    database.print_user_info("admin-2-1-3", certificate);
+
+    print_permission(&database, "admin-2-1-3", certificate);
+
+    if let Err(error) = block_user(&database, "admin-2-1-3", "admin-2-1-3", certificate, &key) {
+        println!("{}", error.full_message())
+    }
+
+    print_permission(&database, "admin-2-1-3", certificate);
+
+    // Unsuccessful (the same user and blocker, already blocked)
+    if let Err(error) = block_user(&database, "admin-2-1-3", "admin-2-1-3", certificate, &key) {
+        println!("{}", error.full_message())
+    }
+
+    // Unsuccessful (blocker is not user's parent)
+    if let Err(error) = block_user(&database, "admin-3-1-3", "admin-2-1-3", certificate, &key) {
+        println!("{}", error.full_message())
+    }
+
+    //////////// Blocking a user by parent, unblocking again////////////////
+    if let Err(error) = block_user(&database, "admin-3-1-3", "admin-3-1", certificate, &key) {
+        println!("{}", error.full_message())
+    }
+
+    print_permission(&database, "admin-3-1-3-1-1", certificate);
+
+    if let Err(error) = unblock_user(&database, "admin-3-1-3", "admin-3-1", certificate, &key) {
+        println!("{}", error.full_message())
+    }
+
+    print_permission(&database, "admin-3-1-3-1-1", certificate);
+
+    //////////// Blocking a user by parent, unblocking by another user////////////////
+    if let Err(error) = block_user(&database, "admin-4-1-3", "admin-4-1", certificate, &key) {
+        println!("{}", error.full_message())
+    }
+
+    print_permission(&database, "admin-4-1-3", certificate);
+
+    if let Err(error) = unblock_user(&database, "admin-4-1-3", "admin-5-1", certificate, &key) {
+        println!("{}", error.full_message())
+    }
+
+    database.print_user_info("admin-4-1-3", certificate);
+
+    database.print_statistics();
+    database.print_user_tree_info(certificate);
+}
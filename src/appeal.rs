@@ -0,0 +1,240 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::names::names;
+
+/// Where an appeal against a block stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AppealState {
+    /// Waiting for the reviewers' votes.
+    Pending,
+    /// The block was lifted.
+    Accepted,
+    /// The block stays.
+    Rejected,
+    /// The block ended or was replaced before the reviewers decided.
+    Lapsed,
+}
+
+names! {
+    /// Error parsing an appeal state.
+    AppealState, ParseAppealStateError {
+        Pending => "pending",
+        Accepted => "accepted",
+        Rejected => "rejected",
+        Lapsed => "lapsed",
+    }
+}
+
+/// A reviewer's vote on an appeal.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Vote {
+    pub reviewer: String,
+    pub accept: bool,
+    /// Unix time the vote was applied at.
+    pub time: u64,
+}
+
+/// A blocked user's appeal against its block, routed to the ancestors of the
+/// user that blocked it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Appeal {
+    /// Position of the appeal among the tree's appeals.
+    pub id: u64,
+    pub user_id: String,
+    pub statement: String,
+    /// Unix time the appeal was filed at.
+    pub filed_at: u64,
+    /// None when a policy rule blocked the user.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blocker: Option<String>,
+    /// Users that may vote on the appeal, nearest ancestor first.
+    pub reviewers: Vec<String>,
+    /// Reviewer whose vote alone decides the appeal.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub designated: Option<String>,
+    /// Number of reviewers that must accept the appeal.
+    pub quorum: usize,
+    pub state: AppealState,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub votes: Vec<Vote>,
+    /// Unix time the appeal was accepted, rejected or lapsed at.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decided_at: Option<u64>,
+}
+
+impl Appeal {
+    pub fn is_pending(&self) -> bool {
+        self.state == AppealState::Pending
+    }
+
+    /// The decision the votes so far reach: accepted once the designated
+    /// reviewer or a quorum accepted, rejected once the designated reviewer
+    /// rejected or a quorum can no longer be reached.
+    pub fn decision(&self) -> Option<bool> {
+        if let Some(vote) = self
+            .votes
+            .iter()
+            .find(|vote| Some(&vote.reviewer) == self.designated.as_ref())
+        {
+            return Some(vote.accept);
+        }
+
+        let accepted = self.votes.iter().filter(|vote| vote.accept).count();
+        let rejected = self.votes.len() - accepted;
+
+        if accepted >= self.quorum {
+            Some(true)
+        } else if rejected > self.reviewers.len().saturating_sub(self.quorum) {
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Appeal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "appeal {} of {} filed at {}, {}",
+            self.id, self.user_id, self.filed_at, self.state
+        )?;
+
+        if let Some(decided_at) = self.decided_at {
+            write!(f, " at {decided_at}")?;
+        }
+
+        write!(f, ": {}", self.statement)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::event::Event;
+    use crate::policy::{AppealPolicy, Policy};
+    use crate::testing::{Fixture, CERTIFICATE};
+    use crate::user::ADMIN_ID;
+
+    /// dave, blocked by its parent carol, appeals to alice and the admin.
+    fn appealed(policy: AppealPolicy) -> (Fixture, Appeal) {
+        let fixture = Fixture::with_users(&[("alice", ADMIN_ID), ("carol", "alice"), ("dave", "carol")]);
+        let policy = Policy {
+            appeals: policy,
+            ..Policy::default()
+        };
+
+        fixture
+            .submit(Event::SetPolicy {
+                certificate: CERTIFICATE.to_owned(),
+                policy,
+            })
+            .unwrap();
+        fixture.block("dave", "carol", None).unwrap();
+
+        let event = Event::FileAppeal {
+            certificate: CERTIFICATE.to_owned(),
+            user_id: "dave".to_owned(),
+            statement: "it was a joke".to_owned(),
+        };
+        let authorization = fixture.database.authorize(&event, fixture.key("dave"));
+        let appeal = fixture
+            .database
+            .file_appeal("dave", "it was a joke", CERTIFICATE, authorization)
+            .unwrap();
+
+        (fixture, appeal)
+    }
+
+    fn review(fixture: &Fixture, reviewer: &str, accept: bool) -> crate::error::Result<Appeal> {
+        let event = fixture.database.review_event(0, reviewer, accept, false, CERTIFICATE);
+        let authorization = fixture.database.authorize(&event, fixture.key(reviewer));
+
+        fixture
+            .database
+            .review_appeal(0, reviewer, accept, false, CERTIFICATE, authorization)
+    }
+
+    #[test]
+    fn appeals_go_to_the_blockers_ancestors() {
+        let (fixture, appeal) = appealed(AppealPolicy::default());
+
+        assert_eq!(appeal.id, 0);
+        assert_eq!(appeal.blocker.as_deref(), Some("carol"));
+        assert_eq!(appeal.reviewers, ["alice", ADMIN_ID]);
+        assert!(appeal.is_pending());
+
+        let event = Event::FileAppeal {
+            certificate: CERTIFICATE.to_owned(),
+            user_id: "dave".to_owned(),
+            statement: "again".to_owned(),
+        };
+        assert!(matches!(
+            fixture.submit(event),
+            Err(Error::AppealPending { appeal: 0, .. })
+        ));
+        assert!(matches!(
+            review(&fixture, "carol", true),
+            Err(Error::NotReviewer { .. })
+        ));
+    }
+
+    #[test]
+    fn a_quorum_of_reviewers_lifts_the_block() {
+        let (fixture, _) = appealed(AppealPolicy {
+            reviewer: None,
+            quorum: 2,
+        });
+
+        let appeal = review(&fixture, "alice", true).unwrap();
+        assert!(appeal.is_pending());
+        assert!(!fixture.permitted("dave"));
+        assert!(matches!(
+            review(&fixture, "alice", true),
+            Err(Error::AlreadyReviewed { .. })
+        ));
+
+        let appeal = review(&fixture, ADMIN_ID, true).unwrap();
+        assert_eq!(appeal.state, AppealState::Accepted);
+        assert!(fixture.permitted("dave"));
+
+        // The deciding reviewer takes the user in
+        let dave = fixture.database.user_info("dave", CERTIFICATE).unwrap().user;
+        assert_eq!(dave.parent, ADMIN_ID);
+        assert!(matches!(
+            review(&fixture, ADMIN_ID, true),
+            Err(Error::AppealClosed { appeal: 0 })
+        ));
+    }
+
+    #[test]
+    fn appeals_fail_once_a_quorum_is_out_of_reach() {
+        let (fixture, _) = appealed(AppealPolicy {
+            reviewer: None,
+            quorum: 2,
+        });
+
+        let appeal = review(&fixture, ADMIN_ID, false).unwrap();
+        assert_eq!(appeal.state, AppealState::Rejected);
+        assert!(!fixture.permitted("dave"));
+    }
+
+    #[test]
+    fn the_designated_reviewer_decides_alone() {
+        let (fixture, appeal) = appealed(AppealPolicy {
+            reviewer: Some("alice".to_owned()),
+            quorum: 2,
+        });
+        assert_eq!(appeal.designated.as_deref(), Some("alice"));
+
+        review(&fixture, ADMIN_ID, true).unwrap();
+        let appeal = review(&fixture, "alice", false).unwrap();
+
+        assert_eq!(appeal.state, AppealState::Rejected);
+        assert!(!fixture.permitted("dave"));
+    }
+}
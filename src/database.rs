@@ -0,0 +1,1114 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard};
+
+use crate::appeal::{Appeal, AppealState};
+use crate::clock::{Clock, SystemClock};
+use crate::delegation::{Limits, Permission};
+use crate::error::{Error, Result};
+use crate::event::{Approval, Authorization, Event, EventLog, FileEventLog, MemoryEventLog, SignedEvent};
+use crate::history::BlockReason;
+use crate::identity::{PublicKey, SecretKey};
+use crate::info::{TreeInfo, UserInfo};
+use crate::invitation::SignedInvitation;
+use crate::merkle::{Digest, InclusionProof};
+use crate::moderation::AuditEntry;
+use crate::policy::Policy;
+use crate::proof::PermissionProof;
+use crate::report::ReportReason;
+use crate::role::{Capability, Role};
+use crate::statistics::Statistics;
+use crate::storage::{FileStorage, MemoryStorage, Storage};
+use crate::transaction::Transaction;
+use crate::tree::{Orphans, UserTree};
+use crate::user::ADMIN_ID;
+
+/// User trees of all certificates, kept in a storage backend and recorded
+/// in an event log.
+///
+/// The database can be shared between threads. Every tree sits behind its
+/// own lock: reads of any trees proceed concurrently, and a mutation only
+/// locks the tree it changes, while storage and log writes are serialized.
+#[derive(Debug, Default)]
+pub struct Database<S: Storage = MemoryStorage, L: EventLog = MemoryEventLog> {
+    trees: RwLock<HashMap<String, Arc<RwLock<UserTree>>>>,
+    storage: Mutex<S>,
+    log: Mutex<L>,
+    clock: Box<dyn Clock>,
+}
+
+// Databases are shared between threads, for example by the server
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Database>();
+    assert_send_sync::<Database<FileStorage, FileEventLog>>();
+};
+
+impl Database {
+    /// Creates an empty in-memory database.
+    pub fn new() -> Database {
+        Database::with_storage(MemoryStorage::new())
+    }
+}
+
+impl<S: Storage> Database<S> {
+    pub fn with_storage(storage: S) -> Database<S> {
+        Database::with_backends(storage, MemoryEventLog::new())
+    }
+}
+
+impl<S: Storage, L: EventLog> Database<S, L> {
+    /// Creates a database holding the trees stored in the storage backend.
+    pub fn with_backends(mut storage: S, log: L) -> Database<S, L> {
+        let trees = storage
+            .load()
+            .into_iter()
+            .map(|tree| (tree.certificate().to_owned(), Arc::new(RwLock::new(tree))))
+            .collect();
+
+        Database {
+            trees: RwLock::new(trees),
+            storage: Mutex::new(storage),
+            log: Mutex::new(log),
+            clock: Box::new(SystemClock),
+        }
+    }
+
+    /// Replaces the system's clock the database stamps operations with and
+    /// checks permissions at.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Database<S, L> {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// Unix time of the database's clock.
+    pub fn now(&self) -> u64 {
+        self.clock.now()
+    }
+
+    /// Opens a database by replaying the events of the log its stored trees
+    /// missed, every event into an empty storage. A tree misses the events
+    /// appended after its last save, for example one that failed.
+    pub fn replay(storage: S, log: L) -> Result<Database<S, L>> {
+        let database = Database::with_backends(storage, log);
+        let events = database.log().events()?;
+
+        for (logged, event) in (1..).zip(events) {
+            let stored = database.read_tree(event.event.certificate(), UserTree::logged).ok();
+
+            // Trees stored before they recorded their log length are up to date
+            if stored.is_some_and(|stored| stored == 0 || stored >= logged) {
+                continue;
+            }
+
+            database.apply(&event, Some(logged))?;
+        }
+
+        Ok(database)
+    }
+
+    pub fn storage(&self) -> MutexGuard<'_, S> {
+        self.storage.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn log(&self) -> MutexGuard<'_, L> {
+        self.log.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Runs read on the user tree of a certificate while holding its read
+    /// lock.
+    pub fn read_tree<T>(&self, certificate: &str, read: impl FnOnce(&UserTree) -> T) -> Result<T> {
+        let tree = self.tree(certificate)?;
+        let tree = tree.read().unwrap_or_else(PoisonError::into_inner);
+        Ok(read(&tree))
+    }
+
+    pub fn certificates(&self) -> Vec<String> {
+        self.trees().keys().cloned().collect()
+    }
+
+    fn trees(&self) -> RwLockReadGuard<'_, HashMap<String, Arc<RwLock<UserTree>>>> {
+        self.trees.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The lock of a certificate's tree, taken out of the map so that the map
+    /// is not locked while the tree is used.
+    fn tree(&self, certificate: &str) -> Result<Arc<RwLock<UserTree>>> {
+        match self.trees().get(certificate) {
+            Some(tree) => Ok(Arc::clone(tree)),
+            None => Err(Error::CertificateNotFound {
+                certificate: certificate.to_owned(),
+            }),
+        }
+    }
+
+    /// Statistics accumulated over all user trees.
+    pub fn statistics(&self) -> Statistics {
+        let trees: Vec<Arc<RwLock<UserTree>>> = self.trees().values().cloned().collect();
+        let mut statistics = Statistics::default();
+
+        for tree in trees {
+            statistics += tree.read().unwrap_or_else(PoisonError::into_inner).statistics();
+        }
+
+        statistics
+    }
+
+    /// Applies a signed event to its user tree, appends it to the log unless
+    /// replaying it from the length the log had once it was appended, and
+    /// then persists the tree. The tree stays locked throughout, so that the
+    /// log keeps the order of each tree's events.
+    fn apply(&self, signed: &SignedEvent, replayed: Option<u64>) -> Result<()> {
+        self.apply_then(signed, replayed, |_| ())
+    }
+
+    /// Applies a signed event like [`Database::apply`], then runs read on its
+    /// changed tree before unlocking it, so that read sees what the event did
+    /// and nothing a later event did.
+    fn apply_then<T>(
+        &self,
+        signed: &SignedEvent,
+        replayed: Option<u64>,
+        read: impl FnOnce(&UserTree) -> T,
+    ) -> Result<T> {
+        let event = &signed.event;
+        let authorization = signed.authorization.as_ref();
+        let certificate = event.certificate();
+
+        if let Event::AddUserTree { admin_key, .. } = event {
+            // Creations hold the log's lock until the tree is in the map, so
+            // that the map is only locked to insert it
+            let mut log = self.log();
+
+            if self.trees().contains_key(certificate) {
+                return Err(Error::TreeAlreadyExists {
+                    certificate: certificate.to_owned(),
+                });
+            }
+
+            let mut tree = UserTree::new(certificate, *admin_key);
+            tree.verify(event, authorization)?;
+            tree.advance_nonce(ADMIN_ID);
+
+            let logged = match replayed {
+                Some(logged) => logged,
+                None => log.append(signed)? + 1,
+            };
+            tree.set_logged(logged);
+
+            let saved = self.storage().save(&tree);
+            let value = read(&tree);
+            let mut trees = self.trees.write().unwrap_or_else(PoisonError::into_inner);
+            trees.insert(certificate.to_owned(), Arc::new(RwLock::new(tree)));
+
+            return saved.map(|()| value);
+        }
+
+        let tree = self.tree(certificate)?;
+        let mut tree = tree.write().unwrap_or_else(PoisonError::into_inner);
+        let logged = tree.atomically(|tree| {
+            apply_to_tree(tree, signed)?;
+            self.append(std::slice::from_ref(signed), replayed)
+        })?;
+
+        self.save(&mut tree, logged)?;
+        Ok(read(&tree))
+    }
+
+    /// Appends applied events to the log, unless replaying, returning the
+    /// log's length. A failing append fails the atomic change applying them,
+    /// which leaves their tree as it was.
+    fn append(&self, events: &[SignedEvent], replayed: Option<u64>) -> Result<u64> {
+        match replayed {
+            Some(logged) => Ok(logged),
+            None => {
+                let mut log = self.log();
+                log.append_all(events)?;
+                Ok(log.len())
+            }
+        }
+    }
+
+    /// Saves a tree that applied the log up to its length logged. The log
+    /// records what happened, so a failing save keeps the tree's changes in
+    /// memory, and the tree's next save or the database's next replay
+    /// catches its file up.
+    fn save(&self, tree: &mut UserTree, logged: u64) -> Result<()> {
+        tree.set_logged(logged);
+        self.storage().save(tree)
+    }
+
+    /// Applies a signed event, for example one received from a peer, at the
+    /// current time and appends it to the log once it succeeded.
+    pub fn submit(&self, mut signed: SignedEvent) -> Result<()> {
+        signed.applied_at = Some(self.now());
+        self.apply(&signed, None)
+    }
+
+    /// Applies signed events to the user tree of their certificate as one
+    /// transaction, at the current time. Either every event is applied and
+    /// appended to the log, or none is and the tree is left as it was.
+    pub fn submit_transaction(&self, mut events: Vec<SignedEvent>) -> Result<()> {
+        let certificate = match events.first() {
+            Some(signed) => signed.event.certificate().to_owned(),
+            None => return Ok(()),
+        };
+
+        if let Some(signed) = events.iter().find(|signed| signed.event.certificate() != certificate) {
+            return Err(Error::CertificateMismatch {
+                expected: certificate,
+                found: signed.event.certificate().to_owned(),
+            });
+        }
+
+        let applied_at = self.now();
+
+        for signed in events.iter_mut() {
+            signed.applied_at = Some(applied_at);
+        }
+
+        // A failing event undoes the events before it
+        let tree = self.tree(&certificate)?;
+        let mut tree = tree.write().unwrap_or_else(PoisonError::into_inner);
+        let logged = tree.atomically(|tree| {
+            for signed in events.iter() {
+                apply_to_tree(tree, signed)?;
+            }
+
+            self.append(&events, None)
+        })?;
+
+        self.save(&mut tree, logged)
+    }
+
+    /// Starts a transaction of operations on the user tree of a certificate.
+    pub fn transaction(&self, certificate: &str) -> Transaction<'_, S, L> {
+        Transaction::new(self, certificate)
+    }
+
+    fn commit(&self, event: Event, authorization: Option<Authorization>) -> Result<()> {
+        self.commit_then(event, authorization, |_| ())
+    }
+
+    /// Commits an event at the current time, then runs read on its changed
+    /// tree, see [`Database::apply_then`].
+    fn commit_then<T>(
+        &self,
+        event: Event,
+        authorization: Option<Authorization>,
+        read: impl FnOnce(&UserTree) -> T,
+    ) -> Result<T> {
+        let signed = SignedEvent {
+            event,
+            authorization,
+            approvals: Vec::new(),
+            applied_at: Some(self.now()),
+        };
+
+        self.apply_then(&signed, None, read)
+    }
+
+    /// Current nonce of a user, which its next signed operation must use.
+    pub fn nonce(&self, user_id: &str, certificate: &str) -> Result<u64> {
+        Ok(self.user_info(user_id, certificate)?.user.nonce)
+    }
+
+    /// Signs an event with the actor's key for the actor's current nonce,
+    /// or for nonce 0 if the event introduces the actor.
+    pub fn authorize(&self, event: &Event, key: &SecretKey) -> Authorization {
+        let nonce = match (event, event.actor()) {
+            (Event::AddUserTree { .. } | Event::RedeemInvitation { .. }, _) | (_, None) => 0,
+            (_, Some(actor)) => self.nonce(actor, event.certificate()).unwrap_or(0),
+        };
+
+        Authorization::sign(event, nonce, key)
+    }
+
+    /// Adds a new user tree to the database based on certificate, signed by
+    /// the admin's key.
+    pub fn add_user_tree(&self, certificate: &str, admin_key: PublicKey, authorization: Authorization) -> Result<()> {
+        let event = Event::AddUserTree {
+            certificate: certificate.to_owned(),
+            admin_key,
+        };
+
+        self.commit(event, Some(authorization))
+    }
+
+    /// Returns the capabilities and limits a user effectively holds in a user
+    /// tree now, no capabilities if it does not have permission.
+    pub fn check_user_permission(&self, user_id: &str, certificate: &str) -> Result<Permission> {
+        self.read_tree(certificate, |tree| tree.check_user_permission_at(user_id, self.now()))?
+    }
+
+    /// Add a user to user tree in the database based on certificate with a
+    /// role and limits granted by the parent, signed by the parent's key.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_user(
+        &self,
+        user_id: &str,
+        parent: &str,
+        role: Role,
+        limits: Limits,
+        public_key: PublicKey,
+        certificate: &str,
+        authorization: Authorization,
+    ) -> Result<()> {
+        let event = Event::AddUser {
+            certificate: certificate.to_owned(),
+            user_id: user_id.to_owned(),
+            parent: parent.to_owned(),
+            role,
+            limits,
+            public_key,
+        };
+
+        self.commit(event, Some(authorization))
+    }
+
+    /// Adds a user under the parent that invited it, signed by the key the
+    /// user joins with. The invitation must not have expired yet.
+    pub fn redeem_invitation(
+        &self,
+        invitation: SignedInvitation,
+        user_id: &str,
+        public_key: PublicKey,
+        authorization: Authorization,
+    ) -> Result<()> {
+        let event = Event::RedeemInvitation {
+            user_id: user_id.to_owned(),
+            public_key,
+            invitation,
+        };
+
+        self.commit(event, Some(authorization))
+    }
+
+    /// Reports a user for a reason, signed by the reporter's key. Repeated
+    /// reports by the same reporter replace the reason and text of the
+    /// earlier one.
+    pub fn report_user(
+        &self,
+        user_id: &str,
+        reporter: &str,
+        reason: ReportReason,
+        text: Option<&str>,
+        certificate: &str,
+        authorization: Authorization,
+    ) -> Result<()> {
+        let event = Event::ReportUser {
+            certificate: certificate.to_owned(),
+            user_id: user_id.to_owned(),
+            reporter: reporter.to_owned(),
+            reason,
+            text: text.map(str::to_owned),
+        };
+
+        self.commit(event, Some(authorization))
+    }
+
+    /// Blocks a user from having permission in a certificate tree, for good
+    /// or for a number of seconds, signed by the blocker's key.
+    #[allow(clippy::too_many_arguments)]
+    pub fn block_user(
+        &self,
+        user_id: &str,
+        blocker: &str,
+        duration: Option<u64>,
+        reason: BlockReason,
+        text: Option<&str>,
+        certificate: &str,
+        authorization: Authorization,
+    ) -> Result<()> {
+        let event = Event::BlockUser {
+            certificate: certificate.to_owned(),
+            user_id: user_id.to_owned(),
+            blocker: blocker.to_owned(),
+            duration,
+            reason,
+            text: text.map(str::to_owned),
+        };
+
+        self.commit(event, Some(authorization))
+    }
+
+    /// Unblocks a user and moves it under the unblocker in a certificate tree.
+    ///
+    /// The unblocker signs the event naming the user's current parent as
+    /// `previous_parent`, see [`Database::unblock_event`].
+    pub fn unblock_user(
+        &self,
+        user_id: &str,
+        unblocker: &str,
+        reason: BlockReason,
+        text: Option<&str>,
+        certificate: &str,
+        authorization: Authorization,
+    ) -> Result<()> {
+        let event = self.unblock_event(user_id, unblocker, reason, text, certificate);
+        self.commit(event, Some(authorization))
+    }
+
+    /// Removes a user from a certificate tree, handling its children as
+    /// orphans says, signed by the remover's key and carrying the approval of
+    /// the children's new parent unless the remover consents for it.
+    ///
+    /// The remover signs the event naming the user's parent and children,
+    /// see [`Database::remove_event`], and approvers sign it for the
+    /// remover's nonce, see [`Database::approve`].
+    pub fn remove_user(
+        &self,
+        user_id: &str,
+        remover: &str,
+        orphans: Orphans,
+        certificate: &str,
+        authorization: Authorization,
+        approvals: Vec<Approval>,
+    ) -> Result<()> {
+        self.submit(SignedEvent {
+            event: self.remove_event(user_id, remover, orphans, certificate),
+            authorization: Some(authorization),
+            approvals,
+            applied_at: None,
+        })
+    }
+
+    /// The event removing a user in the current state of the tree.
+    pub fn remove_event(&self, user_id: &str, remover: &str, orphans: Orphans, certificate: &str) -> Event {
+        let (parent, children) = self
+            .read_tree(certificate, |tree| removal(tree, user_id, &orphans))
+            .ok()
+            .flatten()
+            .unwrap_or_default();
+
+        Event::RemoveUser {
+            certificate: certificate.to_owned(),
+            user_id: user_id.to_owned(),
+            remover: remover.to_owned(),
+            orphans,
+            parent,
+            children,
+        }
+    }
+
+    /// Moves a user and its subtree under a new parent, signed by the mover's
+    /// key and carrying the approvals the tree's move policy asks for.
+    ///
+    /// The mover signs the event naming the user's key, see
+    /// [`Database::move_event`], and approvers sign it for the mover's nonce,
+    /// see [`Database::approve`].
+    pub fn move_user(
+        &self,
+        user_id: &str,
+        new_parent: &str,
+        mover: &str,
+        certificate: &str,
+        authorization: Authorization,
+        approvals: Vec<Approval>,
+    ) -> Result<()> {
+        self.submit(SignedEvent {
+            event: self.move_event(user_id, new_parent, mover, certificate),
+            authorization: Some(authorization),
+            approvals,
+            applied_at: None,
+        })
+    }
+
+    /// The event moving a user in the current state of the tree.
+    pub fn move_event(&self, user_id: &str, new_parent: &str, mover: &str, certificate: &str) -> Event {
+        let (user, limits) = self
+            .read_tree(certificate, |tree| (tree.get(user_id), tree.inherited_limits(user_id)))
+            .unwrap_or((None, Ok(Limits::NONE)));
+
+        Event::MoveUser {
+            certificate: certificate.to_owned(),
+            user_id: user_id.to_owned(),
+            new_parent: new_parent.to_owned(),
+            mover: mover.to_owned(),
+            public_key: user
+                .map(|user| user.public_key)
+                .unwrap_or(PublicKey::from_bytes([0; 32])),
+            limits: limits.unwrap_or(Limits::NONE),
+        }
+    }
+
+    /// Signs another user's approval of an event for the actor's current
+    /// nonce.
+    pub fn approve(&self, event: &Event, user_id: &str, key: &SecretKey) -> Approval {
+        Approval::sign(event, self.authorize(event, key).nonce, user_id, key)
+    }
+
+    /// Replaces the rules of a certificate tree, signed by the admin's key.
+    pub fn set_policy(&self, policy: Policy, certificate: &str, authorization: Authorization) -> Result<()> {
+        let event = Event::SetPolicy {
+            certificate: certificate.to_owned(),
+            policy,
+        };
+
+        self.commit(event, Some(authorization))
+    }
+
+    /// Files a blocked user's appeal against its block, signed by the user's
+    /// key, and returns it.
+    pub fn file_appeal(
+        &self,
+        user_id: &str,
+        statement: &str,
+        certificate: &str,
+        authorization: Authorization,
+    ) -> Result<Appeal> {
+        let event = Event::FileAppeal {
+            certificate: certificate.to_owned(),
+            user_id: user_id.to_owned(),
+            statement: statement.to_owned(),
+        };
+
+        self.commit_then(event, Some(authorization), |tree| {
+            tree.appeals().last().cloned().expect("the appeal was just filed")
+        })
+    }
+
+    /// Votes on an appeal as one of its reviewers, signed by the reviewer's
+    /// key, and returns the appeal with the vote. An accepted appeal moves the
+    /// user under the deciding reviewer unless it keeps its parent.
+    ///
+    /// The reviewer signs the event naming the appealing user's key, see
+    /// [`Database::review_event`].
+    pub fn review_appeal(
+        &self,
+        appeal: u64,
+        reviewer: &str,
+        accept: bool,
+        keep_parent: bool,
+        certificate: &str,
+        authorization: Authorization,
+    ) -> Result<Appeal> {
+        let event = self.review_event(appeal, reviewer, accept, keep_parent, certificate);
+        self.commit_then(event, Some(authorization), |tree| {
+            tree.appeal(appeal).cloned().expect("the appeal was just reviewed")
+        })
+    }
+
+    /// The event reviewing an appeal in the current state of the tree.
+    pub fn review_event(
+        &self,
+        appeal: u64,
+        reviewer: &str,
+        accept: bool,
+        keep_parent: bool,
+        certificate: &str,
+    ) -> Event {
+        let user = self
+            .read_tree(certificate, |tree| {
+                let user_id = &tree.appeal(appeal).ok()?.user_id;
+                tree.get(user_id)
+            })
+            .ok()
+            .flatten();
+
+        Event::ReviewAppeal {
+            certificate: certificate.to_owned(),
+            appeal,
+            user_id: user.as_ref().map(|user| user.id.clone()).unwrap_or_default(),
+            public_key: user
+                .map(|user| user.public_key)
+                .unwrap_or(PublicKey::from_bytes([0; 32])),
+            reviewer: reviewer.to_owned(),
+            accept,
+            keep_parent,
+        }
+    }
+
+    /// Every appeal filed in a certificate tree, oldest first.
+    pub fn appeals(&self, certificate: &str) -> Result<Vec<Appeal>> {
+        self.read_tree(certificate, |tree| tree.appeals().to_vec())
+    }
+
+    pub fn appeal(&self, appeal: u64, certificate: &str) -> Result<Appeal> {
+        self.read_tree(certificate, |tree| tree.appeal(appeal).cloned())?
+    }
+
+    /// The event unblocking a user in the current state of the tree.
+    pub fn unblock_event(
+        &self,
+        user_id: &str,
+        unblocker: &str,
+        reason: BlockReason,
+        text: Option<&str>,
+        certificate: &str,
+    ) -> Event {
+        let user = self.read_tree(certificate, |tree| tree.get(user_id)).ok().flatten();
+
+        Event::UnblockUser {
+            certificate: certificate.to_owned(),
+            user_id: user_id.to_owned(),
+            unblocker: unblocker.to_owned(),
+            previous_parent: user.as_ref().map(|user| user.parent.clone()).unwrap_or_default(),
+            public_key: user
+                .map(|user| user.public_key)
+                .unwrap_or(PublicKey::from_bytes([0; 32])),
+            reason,
+            text: text.map(str::to_owned),
+        }
+    }
+
+    /// Creates a hierarchical user tree for testing purposes, where every
+    /// user holds the admin's key.
+    pub fn make_user_tree_test(&self, branch: u16, level: u16, certificate: &str, key: &SecretKey) {
+        let mut parents: Vec<String> = vec![ADMIN_ID.to_owned()];
+
+        for _l in 1..level {
+            let mut next_parents: Vec<String> = Vec::new();
+
+            for parent in parents.iter() {
+                for b in 1..branch {
+                    let id = format!("{parent}-{b}");
+                    let event = Event::AddUser {
+                        certificate: certificate.to_owned(),
+                        user_id: id.clone(),
+                        parent: parent.clone(),
+                        role: Role::Moderator,
+                        limits: Limits::NONE,
+                        public_key: key.public_key(),
+                    };
+
+                    let authorization = self.authorize(&event, key);
+                    let _ = self.commit(event, Some(authorization));
+
+                    next_parents.push(id)
+                }
+            }
+
+            parents = next_parents;
+        }
+    }
+
+    /// Exports the chain of signed grants from the admin to a user that has
+    /// permission.
+    pub fn permission_proof(&self, user_id: &str, certificate: &str) -> Result<PermissionProof> {
+        if !self.check_user_permission(user_id, certificate)?.is_granted() {
+            return Err(Error::PermissionDenied {
+                user_id: user_id.to_owned(),
+                certificate: certificate.to_owned(),
+            });
+        }
+
+        let tree = self.tree(certificate)?;
+        let tree = tree.read().unwrap_or_else(PoisonError::into_inner);
+        let mut grants = Vec::new();
+        let mut current_id = user_id.to_owned();
+
+        while let Some(user) = tree.get(&current_id) {
+            if user.is_admin() {
+                break;
+            }
+
+            match &user.grant {
+                Some(grant) => grants.push(grant.clone()),
+                None => {
+                    return Err(Error::InvalidProof {
+                        user_id: user_id.to_owned(),
+                        reason: "user without a signed grant in chain",
+                    })
+                }
+            }
+
+            current_id = user.parent;
+        }
+
+        grants.reverse();
+
+        Ok(PermissionProof {
+            certificate: certificate.to_owned(),
+            user_id: user_id.to_owned(),
+            grants,
+        })
+    }
+
+    /// Ids of the blocked and removed users of a certificate, for rejecting
+    /// permission proofs through them. Users whose temporary block ended are
+    /// not revoked.
+    pub fn revocation_list(&self, certificate: &str) -> Result<BTreeSet<String>> {
+        let now = self.now();
+
+        self.read_tree(certificate, |tree| {
+            let blocked = tree.users().filter(|user| user.is_blocked_at(now)).map(|user| user.id);
+            blocked.chain(tree.removed_users().map(str::to_owned)).collect()
+        })
+    }
+
+    /// Actions the policy's rules of a certificate took by themselves, oldest
+    /// first.
+    pub fn audit_trail(&self, certificate: &str) -> Result<Vec<AuditEntry>> {
+        self.read_tree(certificate, |tree| tree.audit_trail().to_vec())
+    }
+
+    /// Information about a specific user.
+    pub fn user_info(&self, user_id: &str, certificate: &str) -> Result<UserInfo> {
+        let now = self.now();
+
+        self.read_tree(certificate, |tree| {
+            let score = tree.report_score(user_id, now)?;
+            let mut user = tree.get(user_id).expect("scored users exist");
+            user.suspended = tree.user_suspended_at(user_id, now)?;
+
+            if !user.suspended.contains(Capability::Invite) {
+                user.suspended_by = None;
+            }
+
+            // A temporary block that ended shows as none
+            if !user.is_blocked_at(now) {
+                user.blocked = false;
+                user.blocked_until = None;
+            }
+
+            Ok(UserInfo {
+                certificate: certificate.to_owned(),
+                user,
+                score,
+            })
+        })?
+    }
+
+    /// Information about the user tree of a certificate.
+    pub fn tree_info(&self, certificate: &str) -> Result<TreeInfo> {
+        let now = self.now();
+        self.read_tree(certificate, |tree| TreeInfo::new(tree, now))
+    }
+
+    /// Merkle root committing to every user of a certificate.
+    pub fn tree_root(&self, certificate: &str) -> Result<Digest> {
+        self.read_tree(certificate, UserTree::root)
+    }
+
+    /// Proof that a user's record is committed to by the certificate's root.
+    pub fn inclusion_proof(&self, user_id: &str, certificate: &str) -> Result<InclusionProof> {
+        self.read_tree(certificate, |tree| tree.inclusion_proof(user_id))?
+    }
+
+    /// Prints information about a specific user.
+    pub fn print_user_info(&self, id: &str, certificate: &str) {
+        match self.user_info(id, certificate) {
+            Ok(info) => print_user_info(&info),
+            Err(error) => println!("{error}"),
+        }
+    }
+
+    pub fn print_statistics(&self) {
+        print_statistics(&self.statistics());
+    }
+
+    pub fn print_user_tree_info(&self, certificate: &str) {
+        match self.tree_info(certificate) {
+            Ok(info) => print_user_tree_info(&info),
+            Err(error) => {
+                println!("***************User Tree Information***************");
+                println!("{error}");
+            }
+        }
+    }
+}
+
+/// The parent of a user to remove and, unless its subtree is removed with it,
+/// its children with their keys.
+fn removal(tree: &UserTree, user_id: &str, orphans: &Orphans) -> Option<(String, BTreeMap<String, PublicKey>)> {
+    let user = tree.get(user_id)?;
+    let children = match orphans {
+        Orphans::Cascade => BTreeMap::new(),
+        _ => user
+            .children
+            .iter()
+            .filter_map(|child| Some((child.clone(), tree.get(child)?.public_key)))
+            .collect(),
+    };
+
+    Some((user.parent, children))
+}
+
+/// Applies a signed event to an existing user tree.
+fn apply_to_tree(tree: &mut UserTree, signed: &SignedEvent) -> Result<()> {
+    let event = &signed.event;
+    let authorization = signed.authorization.as_ref();
+
+    tree.set_time(signed.applied_at.unwrap_or(0));
+    tree.verify(event, authorization)?;
+
+    match event {
+        Event::AddUserTree { certificate, .. } => {
+            return Err(Error::TreeAlreadyExists {
+                certificate: certificate.clone(),
+            })
+        }
+        Event::AddUser {
+            user_id,
+            parent,
+            role,
+            limits,
+            public_key,
+            ..
+        } => tree.add_user(user_id, parent, *role, *limits, *public_key)?,
+        Event::RedeemInvitation {
+            user_id,
+            public_key,
+            invitation,
+        } => tree.redeem_invitation(invitation, user_id, *public_key)?,
+        Event::ReportUser {
+            user_id,
+            reporter,
+            reason,
+            text,
+            ..
+        } => tree.report_user(user_id, reporter, *reason, text.as_deref())?,
+        Event::BlockUser {
+            user_id,
+            blocker,
+            duration,
+            reason,
+            text,
+            ..
+        } => tree.block_user(user_id, blocker, *duration, *reason, text.as_deref())?,
+        Event::UnblockUser {
+            user_id,
+            unblocker,
+            public_key,
+            reason,
+            text,
+            ..
+        } => {
+            if tree.get(user_id).is_some_and(|user| user.public_key != *public_key) {
+                return Err(Error::InvalidSignature {
+                    user_id: unblocker.clone(),
+                });
+            }
+
+            tree.unblock_user(user_id, unblocker, *reason, text.as_deref())?
+        }
+        Event::RemoveUser {
+            user_id,
+            remover,
+            orphans,
+            parent,
+            children,
+            ..
+        } => {
+            // Events logged before removals named the parent name nothing
+            if !parent.is_empty()
+                && removal(tree, user_id, orphans).is_some_and(|(actual_parent, actual_children)| {
+                    (&actual_parent, &actual_children) != (parent, children)
+                })
+            {
+                return Err(Error::InvalidSignature {
+                    user_id: remover.clone(),
+                });
+            }
+
+            let approvers = tree.verify_approvals(event, authorization, &signed.approvals)?;
+            tree.remove_user(user_id, remover, orphans, &approvers)?
+        }
+        Event::MoveUser {
+            user_id,
+            new_parent,
+            mover,
+            public_key,
+            limits,
+            ..
+        } => {
+            if tree.get(user_id).is_some_and(|user| user.public_key != *public_key) {
+                return Err(Error::InvalidSignature { user_id: mover.clone() });
+            }
+
+            // Moves recorded before moves carried limits name none
+            if !limits.is_none()
+                && tree
+                    .inherited_limits(user_id)
+                    .is_ok_and(|inherited| inherited != *limits)
+            {
+                return Err(Error::InvalidSignature { user_id: mover.clone() });
+            }
+
+            let approvers = tree.verify_approvals(event, authorization, &signed.approvals)?;
+            tree.move_user(user_id, new_parent, mover, &approvers)?
+        }
+        Event::SetPolicy { policy, .. } => tree.set_policy(policy.clone()),
+        Event::FileAppeal { user_id, statement, .. } => tree.file_appeal(user_id, statement)?,
+        Event::ReviewAppeal {
+            appeal,
+            user_id,
+            public_key,
+            reviewer,
+            accept,
+            keep_parent,
+            ..
+        } => {
+            if tree.appeal(*appeal)?.user_id != *user_id
+                || tree.get(user_id).is_some_and(|user| user.public_key != *public_key)
+            {
+                return Err(Error::InvalidSignature {
+                    user_id: reviewer.clone(),
+                });
+            }
+
+            tree.review_appeal(*appeal, reviewer, *accept, *keep_parent)?
+        }
+    };
+
+    if let Some(actor) = event.actor() {
+        tree.advance_nonce(actor);
+    }
+
+    match event {
+        Event::AddUser { user_id, .. }
+        | Event::RedeemInvitation { user_id, .. }
+        | Event::UnblockUser { user_id, .. }
+        // The new parent signed or approved every move
+        | Event::MoveUser { user_id, .. } => tree.set_grant(user_id, signed.clone()),
+        // The deciding reviewer of an accepted appeal is the user's new parent
+        Event::ReviewAppeal {
+            appeal,
+            user_id,
+            keep_parent: false,
+            ..
+        } if tree
+            .appeal(*appeal)
+            .is_ok_and(|appeal| appeal.state == AppealState::Accepted) =>
+        {
+            tree.set_grant(user_id, signed.clone())
+        }
+        // The new parent or one of its ancestors signed or approved every
+        // removal moving children
+        Event::RemoveUser { children, .. } => {
+            for child in children.keys() {
+                tree.set_grant(child, signed.clone());
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Prints information about a specific user.
+pub fn print_user_info(info: &UserInfo) {
+    let user = &info.user;
+
+    println!("############ User Info ############");
+    println!("user id: {}", user.id);
+    println!("user certificate: {}", info.certificate);
+    println!("user parent: {}", user.parent);
+    println!("user children: {:?}", user.children);
+    println!("user reports: {}", user.reports);
+    println!(
+        "user report score: {:.2} direct, {:.2} propagated",
+        info.score.direct, info.score.propagated
+    );
+
+    for report in user.report_log.iter() {
+        match &report.text {
+            Some(text) => println!(
+                "  reported by {} for {} at {}: {text}",
+                report.reporter, report.reason, report.time
+            ),
+            None => println!(
+                "  reported by {} for {} at {}",
+                report.reporter, report.reason, report.time
+            ),
+        }
+    }
+
+    println!("user blocked: {}", user.blocked);
+
+    if let Some(until) = user.blocked_until {
+        println!("user blocked until: {until}");
+    }
+
+    for record in user.block_history.iter() {
+        println!("  {record}");
+    }
+
+    println!("user role: {}", user.role);
+
+    if !user.suspended.is_empty() {
+        println!("user suspended capabilities: {}", user.suspended);
+    }
+
+    println!("user limits: {}", user.limits);
+    println!("user public key: {}", user.public_key);
+    println!("user nonce: {}", user.nonce);
+}
+
+pub fn print_statistics(statistics: &Statistics) {
+    println!("***************Statistics***************");
+    println!("Number of adding user: {}", statistics.user_add);
+    println!("Number of reading user: {}", statistics.user_read);
+    println!("Number of updating user: {}", statistics.user_update);
+}
+
+pub fn print_user_tree_info(info: &TreeInfo) {
+    let certificate = &info.certificate;
+
+    println!("***************User Tree Information***************");
+    println!("Number of users under certificate '{}': '{}'", certificate, info.users);
+    println!(
+        "Number of blocked users under certificate '{}': '{}'",
+        certificate, info.blocked
+    );
+    println!(
+        "Average of users' children under certificate '{}': '{}' (included childless)",
+        certificate, info.average_children
+    );
+    println!(
+        "Average of users' children under certificate '{}': '{}' (excluded childless)",
+        certificate, info.average_children_excluded_childless
+    );
+    println!("Root hash of certificate '{}': '{}'", certificate, info.root);
+    println!(
+        "Policy of certificate '{}': '{}'",
+        certificate,
+        serde_json::to_string(&info.policy).expect("policies serialize to JSON")
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use crate::event::{Event, EventLog};
+    use crate::report::ReportReason;
+    use crate::testing::{Fixture, CERTIFICATE};
+    use crate::user::ADMIN_ID;
+
+    #[test]
+    fn concurrent_operations_are_all_applied() {
+        let reporters: Vec<String> = (0..8).map(|reporter| format!("reporter-{reporter}")).collect();
+        let mut users: Vec<(&str, &str)> = reporters.iter().map(|reporter| (reporter.as_str(), ADMIN_ID)).collect();
+        users.push(("dave", ADMIN_ID));
+        let fixture = Fixture::with_users(&users);
+
+        thread::scope(|scope| {
+            for reporter in reporters.iter() {
+                let fixture = &fixture;
+
+                scope.spawn(move || {
+                    fixture
+                        .submit(Event::ReportUser {
+                            certificate: CERTIFICATE.to_owned(),
+                            user_id: "dave".to_owned(),
+                            reporter: reporter.clone(),
+                            reason: ReportReason::Spam,
+                            text: None,
+                        })
+                        .unwrap();
+                });
+            }
+
+            scope.spawn(|| {
+                for _ in 0..100 {
+                    assert!(fixture.permitted("dave"));
+                }
+            });
+        });
+
+        let dave = fixture.database.user_info("dave", CERTIFICATE).unwrap().user;
+        assert_eq!(dave.reports, 8);
+        assert_eq!(fixture.database.log().len(), 1 + 9 + 8);
+    }
+}
@@ -0,0 +1,137 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::role::Capabilities;
+
+/// Limits a parent places on a child, on top of its role.
+///
+/// Limits apply to the child's whole subtree: a user is bound by the
+/// tightest limit of itself and each of its ancestors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Limits {
+    /// Number of children each user of the subtree may have at most.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_children: Option<u32>,
+    /// Unix time in seconds after which the subtree loses its permission.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub valid_until: Option<u64>,
+}
+
+impl Limits {
+    /// No limits beyond the role.
+    pub const NONE: Limits = Limits {
+        max_children: None,
+        valid_until: None,
+    };
+
+    pub fn is_none(&self) -> bool {
+        *self == Limits::NONE
+    }
+
+    /// The tightest of both limits.
+    pub fn combine(self, other: Limits) -> Limits {
+        Limits {
+            max_children: min_some(self.max_children, other.max_children),
+            valid_until: min_some(self.valid_until, other.valid_until),
+        }
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.valid_until.is_some_and(|valid_until| valid_until < now)
+    }
+
+    /// Checks if a user with children children may add another one.
+    pub fn allows_child(&self, children: usize) -> bool {
+        self.max_children
+            .is_none_or(|max_children| children < max_children as usize)
+    }
+}
+
+fn min_some<T: Ord>(a: Option<T>, b: Option<T>) -> Option<T> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+impl fmt::Display for Limits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut limits = Vec::new();
+
+        if let Some(max_children) = self.max_children {
+            limits.push(format!("at most {max_children} children"));
+        }
+
+        if let Some(valid_until) = self.valid_until {
+            limits.push(format!("valid until {valid_until}"));
+        }
+
+        if limits.is_empty() {
+            f.write_str("none")
+        } else {
+            f.write_str(&limits.join(", "))
+        }
+    }
+}
+
+/// What a user may effectively do, combined along its ancestors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Permission {
+    /// Capabilities the user holds, none if it does not have permission.
+    pub capabilities: Capabilities,
+    pub limits: Limits,
+}
+
+impl Permission {
+    /// The permission of a blocked or expired user.
+    pub const DENIED: Permission = Permission {
+        capabilities: Capabilities::NONE,
+        limits: Limits::NONE,
+    };
+
+    pub fn is_granted(&self) -> bool {
+        !self.capabilities.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::role::Role;
+    use crate::testing::{Fixture, CERTIFICATE};
+    use crate::user::ADMIN_ID;
+
+    #[test]
+    fn limits_bind_the_whole_subtree() {
+        let mut fixture = Fixture::new();
+        let valid_until = fixture.database.now() + 60;
+        let limits = Limits {
+            max_children: Some(1),
+            valid_until: Some(valid_until),
+        };
+        fixture.add_limited("alice", ADMIN_ID, Role::Moderator, limits).unwrap();
+
+        // Looser limits of a child do not lift those of its parent
+        let looser = Limits {
+            max_children: Some(5),
+            valid_until: None,
+        };
+        fixture.add_limited("bob", "alice", Role::Inviter, looser).unwrap();
+        let added = fixture.add("carol", "alice", Role::Member).unwrap_err();
+        assert!(matches!(
+            added.root_cause(),
+            Error::ChildLimitReached { max_children: 1, .. }
+        ));
+
+        let bob = fixture.database.check_user_permission("bob", CERTIFICATE).unwrap();
+        assert_eq!(bob.limits, limits);
+        fixture.add("carol", "bob", Role::Member).unwrap();
+        assert!(fixture.add("dave", "bob", Role::Member).is_err());
+
+        fixture.clock.advance(61);
+        assert!(!fixture.permitted("alice"));
+        assert!(!fixture.permitted("carol"));
+    }
+}
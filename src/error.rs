@@ -0,0 +1,272 @@
+use std::fmt;
+
+use crate::role::Capability;
+
+/// Errors returned by database and user tree operations.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// No user tree exists under the certificate.
+    CertificateNotFound { certificate: String },
+    /// The user does not exist in the certificate tree.
+    UserNotFound { user_id: String, certificate: String },
+    /// A user tree already exists under the certificate.
+    TreeAlreadyExists { certificate: String },
+    /// The user already exists in the certificate tree.
+    UserAlreadyExists { user_id: String, certificate: String },
+    /// A user can not be its own parent.
+    SelfParent { user_id: String },
+    /// The user, or one of its ancestors, is blocked.
+    PermissionDenied { user_id: String, certificate: String },
+    /// The admin of a certificate tree can not be removed.
+    AdminNotRemovable { user_id: String },
+    /// The would-be parent is the user or one of its descendants.
+    ParentInSubtree { user_id: String, parent: String },
+    /// The user is already blocked.
+    AlreadyBlocked { user_id: String },
+    /// The user is not blocked and already has permission.
+    AlreadyPermitted { user_id: String },
+    /// The user is not blocked itself, so it has nothing to appeal.
+    NotBlocked { user_id: String },
+    /// The user's earlier appeal has not been decided yet.
+    AppealPending { user_id: String, appeal: u64 },
+    /// No appeal with the id exists in the certificate tree.
+    AppealNotFound { appeal: u64, certificate: String },
+    /// The appeal has already been decided.
+    AppealClosed { appeal: u64 },
+    /// The user is not among the reviewers of the appeal.
+    NotReviewer { user_id: String, appeal: u64 },
+    /// The reviewer has already voted on the appeal.
+    AlreadyReviewed { user_id: String, appeal: u64 },
+    /// The user lacks a capability the operation, or the role it grants,
+    /// requires.
+    MissingCapability { user_id: String, capability: Capability },
+    /// The user already has as many children as its limits allow.
+    ChildLimitReached { user_id: String, max_children: u32 },
+    /// The operation needs the user's approval, which it does not carry.
+    MissingApproval { user_id: String },
+    /// The actor is not allowed to perform the operation on the user.
+    NotAuthorized { actor: String, user_id: String },
+    /// The operation is not signed by the acting user's key.
+    InvalidSignature { user_id: String },
+    /// The operation is signed for another nonce of the acting user.
+    InvalidNonce { user_id: String, expected: u64, found: u64 },
+    /// The invitation is not signed by its parent or not meant for the user.
+    InvalidInvitation { reason: &'static str },
+    /// The invitation expired before it was redeemed.
+    InvitationExpired { expires_at: u64 },
+    /// The invitation has already been redeemed.
+    InvitationRedeemed { nonce: String },
+    /// A permission proof does not check out against the admin's key.
+    InvalidProof { user_id: String, reason: &'static str },
+    /// An operation on another certificate was added to a transaction.
+    CertificateMismatch { expected: String, found: String },
+    /// The storage backend failed to load or persist the database.
+    Storage { path: String, message: String },
+    /// An operation failed while checking one of the users it involves.
+    Context {
+        role: &'static str,
+        user_id: String,
+        source: Box<Error>,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Broad categories of errors, used to pick exit codes and HTTP statuses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// A certificate or user does not exist.
+    NotFound,
+    /// The operation conflicts with the current state of the tree.
+    Conflict,
+    /// The acting user is not allowed to perform the operation.
+    Unauthorized,
+    /// The storage backend or event log failed.
+    Storage,
+}
+
+impl Error {
+    pub(crate) fn context(self, role: &'static str, user_id: &str) -> Error {
+        Error::Context {
+            role,
+            user_id: user_id.to_owned(),
+            source: Box::new(self),
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        match self.root_cause() {
+            Error::CertificateNotFound { .. } | Error::UserNotFound { .. } | Error::AppealNotFound { .. } => {
+                ErrorKind::NotFound
+            }
+            Error::TreeAlreadyExists { .. }
+            | Error::UserAlreadyExists { .. }
+            | Error::SelfParent { .. }
+            | Error::ParentInSubtree { .. }
+            | Error::AlreadyBlocked { .. }
+            | Error::AlreadyPermitted { .. }
+            | Error::NotBlocked { .. }
+            | Error::AppealPending { .. }
+            | Error::AppealClosed { .. }
+            | Error::AlreadyReviewed { .. }
+            | Error::InvalidNonce { .. }
+            | Error::InvitationRedeemed { .. }
+            | Error::CertificateMismatch { .. } => ErrorKind::Conflict,
+            Error::PermissionDenied { .. }
+            | Error::AdminNotRemovable { .. }
+            | Error::MissingCapability { .. }
+            | Error::ChildLimitReached { .. }
+            | Error::MissingApproval { .. }
+            | Error::NotAuthorized { .. }
+            | Error::NotReviewer { .. }
+            | Error::InvalidSignature { .. }
+            | Error::InvalidInvitation { .. }
+            | Error::InvitationExpired { .. }
+            | Error::InvalidProof { .. } => ErrorKind::Unauthorized,
+            Error::Storage { .. } | Error::Context { .. } => ErrorKind::Storage,
+        }
+    }
+
+    /// Formats the error followed by the errors that caused it.
+    pub fn full_message(&self) -> String {
+        let mut message = self.to_string();
+        let mut source = std::error::Error::source(self);
+
+        while let Some(error) = source {
+            message += &format!(": {error}");
+            source = error.source();
+        }
+
+        message
+    }
+
+    /// Innermost error of a context chain.
+    pub fn root_cause(&self) -> &Error {
+        match self {
+            Error::Context { source, .. } => source.root_cause(),
+            error => error,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::CertificateNotFound { certificate } => {
+                write!(f, "certificate '{certificate}' not found in database.")
+            }
+            Error::UserNotFound { user_id, certificate } => {
+                write!(f, "user '{user_id}' not found in certificate tree '{certificate}'.")
+            }
+            Error::TreeAlreadyExists { certificate } => {
+                write!(
+                    f,
+                    "User tree under certificate '{certificate}' already exists in database."
+                )
+            }
+            Error::UserAlreadyExists { user_id, certificate } => {
+                write!(
+                    f,
+                    "user '{user_id}' already exists in certificate tree '{certificate}'."
+                )
+            }
+            Error::SelfParent { user_id } => {
+                write!(f, "parent id must not be identical to user id '{user_id}'.")
+            }
+            Error::PermissionDenied { user_id, certificate } => {
+                write!(
+                    f,
+                    "user {user_id} does not have permission in certificate tree '{certificate}'."
+                )
+            }
+            Error::AdminNotRemovable { user_id } => write!(f, "user {user_id} is the admin and can not be removed."),
+            Error::ParentInSubtree { user_id, parent } => {
+                write!(
+                    f,
+                    "user {parent} is in the subtree of user {user_id} and can not become a parent of it."
+                )
+            }
+            Error::AlreadyBlocked { user_id } => write!(f, "user {user_id} has already been blocked."),
+            Error::AlreadyPermitted { user_id } => write!(f, "user {user_id} already has permission."),
+            Error::NotBlocked { user_id } => write!(f, "user {user_id} is not blocked."),
+            Error::AppealPending { user_id, appeal } => {
+                write!(f, "appeal {appeal} of user {user_id} has not been decided yet.")
+            }
+            Error::AppealNotFound { appeal, certificate } => {
+                write!(f, "appeal {appeal} not found in certificate tree '{certificate}'.")
+            }
+            Error::AppealClosed { appeal } => write!(f, "appeal {appeal} has already been decided."),
+            Error::NotReviewer { user_id, appeal } => write!(f, "user {user_id} is not a reviewer of appeal {appeal}."),
+            Error::AlreadyReviewed { user_id, appeal } => {
+                write!(f, "user {user_id} has already reviewed appeal {appeal}.")
+            }
+            Error::MissingCapability { user_id, capability } => {
+                write!(f, "user {user_id} does not have the '{capability}' capability.")
+            }
+            Error::ChildLimitReached { user_id, max_children } => {
+                write!(f, "user {user_id} may not have more than {max_children} children.")
+            }
+            Error::MissingApproval { user_id } => write!(f, "operation needs the approval of user {user_id}."),
+            Error::NotAuthorized { actor, user_id } => {
+                write!(f, "user {actor} is not authorized to act on user {user_id}.")
+            }
+            Error::InvalidSignature { user_id } => write!(f, "operation is not signed by user {user_id}."),
+            Error::InvalidNonce {
+                user_id,
+                expected,
+                found,
+            } => {
+                write!(
+                    f,
+                    "operation of user {user_id} is signed for nonce {found}, expected {expected}."
+                )
+            }
+            Error::InvalidInvitation { reason } => write!(f, "invalid invitation: {reason}."),
+            Error::InvitationExpired { expires_at } => write!(f, "invitation expired at {expires_at}."),
+            Error::InvitationRedeemed { nonce } => write!(f, "invitation {nonce} has already been redeemed."),
+            Error::InvalidProof { user_id, reason } => {
+                write!(f, "invalid permission proof for user {user_id}: {reason}.")
+            }
+            Error::CertificateMismatch { expected, found } => {
+                write!(
+                    f,
+                    "operation on certificate '{found}' in a transaction on certificate '{expected}'."
+                )
+            }
+            Error::Storage { path, message } => write!(f, "storage '{path}' failed: {message}"),
+            Error::Context { role, user_id, .. } => write!(f, "Error for {role} {user_id}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Context { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::role::Role;
+    use crate::testing::{Fixture, CERTIFICATE};
+    use crate::user::ADMIN_ID;
+
+    #[test]
+    fn errors_keep_their_kind_through_their_context() {
+        let mut fixture = Fixture::with_users(&[("alice", ADMIN_ID)]);
+        let event = fixture.database.move_event("alice", "ghost", "alice", CERTIFICATE);
+        let error = fixture.submit(event).unwrap_err();
+
+        assert!(matches!(&error, Error::Context { user_id, .. } if user_id == "ghost"));
+        assert!(matches!(error.root_cause(), Error::UserNotFound { user_id, .. } if user_id == "ghost"));
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+        assert!(error.full_message().ends_with(&error.root_cause().to_string()));
+
+        let error = fixture.add("alice", ADMIN_ID, Role::Member).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Conflict);
+    }
+}
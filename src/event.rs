@@ -0,0 +1,482 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::delegation::Limits;
+use crate::error::{Error, Result};
+use crate::history::BlockReason;
+use crate::identity::{PublicKey, SecretKey, Signature};
+use crate::invitation::SignedInvitation;
+use crate::policy::Policy;
+use crate::report::ReportReason;
+use crate::role::Role;
+use crate::tree::Orphans;
+use crate::user::ADMIN_ID;
+
+/// Prefix of every signed message, so that signatures over events can not be
+/// confused with signatures made for anything else.
+const SIGNING_DOMAIN: &[u8] = b"tree-structured-org/event/v1\n";
+
+/// A mutation applied to the database.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    AddUserTree {
+        certificate: String,
+        admin_key: PublicKey,
+    },
+    AddUser {
+        certificate: String,
+        user_id: String,
+        parent: String,
+        /// Omitted when legacy, so that events signed before roles existed
+        /// keep their signatures.
+        #[serde(default = "Role::legacy", skip_serializing_if = "Role::is_legacy")]
+        role: Role,
+        #[serde(default, skip_serializing_if = "Limits::is_none")]
+        limits: Limits,
+        public_key: PublicKey,
+    },
+    RedeemInvitation {
+        user_id: String,
+        public_key: PublicKey,
+        invitation: SignedInvitation,
+    },
+    ReportUser {
+        certificate: String,
+        user_id: String,
+        reporter: String,
+        reason: ReportReason,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        text: Option<String>,
+    },
+    BlockUser {
+        certificate: String,
+        user_id: String,
+        blocker: String,
+        /// Seconds the block lasts, none for good.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        duration: Option<u64>,
+        #[serde(default, skip_serializing_if = "BlockReason::is_default")]
+        reason: BlockReason,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        text: Option<String>,
+    },
+    UnblockUser {
+        certificate: String,
+        user_id: String,
+        unblocker: String,
+        previous_parent: String,
+        /// Key of the unblocked user, so that the event grants it to the
+        /// unblocker like an `AddUser` event does.
+        public_key: PublicKey,
+        #[serde(default, skip_serializing_if = "BlockReason::is_default")]
+        reason: BlockReason,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        text: Option<String>,
+    },
+    RemoveUser {
+        certificate: String,
+        user_id: String,
+        remover: String,
+        orphans: Orphans,
+        /// Parent of the removed user, which its children move under unless
+        /// adopted.
+        #[serde(default, skip_serializing_if = "String::is_empty")]
+        parent: String,
+        /// Keys of the removed user's children, so that the event grants them
+        /// to the user they move under, which signs or approves the removal
+        /// itself or through one of its ancestors.
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        children: BTreeMap<String, PublicKey>,
+    },
+    MoveUser {
+        certificate: String,
+        user_id: String,
+        new_parent: String,
+        mover: String,
+        /// Key of the moved user, so that the event grants it to the new
+        /// parent when the new parent moves it.
+        public_key: PublicKey,
+        /// Limits the moved user keeps from itself and its old ancestors, so
+        /// that proofs through the move see them.
+        #[serde(default, skip_serializing_if = "Limits::is_none")]
+        limits: Limits,
+    },
+    SetPolicy {
+        certificate: String,
+        policy: Policy,
+    },
+    FileAppeal {
+        certificate: String,
+        user_id: String,
+        statement: String,
+    },
+    ReviewAppeal {
+        certificate: String,
+        appeal: u64,
+        /// The appealing user and its key, so that the event grants the user
+        /// to the reviewer an accepted appeal moves it under.
+        user_id: String,
+        public_key: PublicKey,
+        reviewer: String,
+        accept: bool,
+        /// Leaves the user under its parent instead of moving it under the
+        /// reviewer when the appeal is accepted.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        keep_parent: bool,
+    },
+}
+
+impl Event {
+    pub fn certificate(&self) -> &str {
+        match self {
+            Event::AddUserTree { certificate, .. }
+            | Event::AddUser { certificate, .. }
+            | Event::ReportUser { certificate, .. }
+            | Event::BlockUser { certificate, .. }
+            | Event::UnblockUser { certificate, .. }
+            | Event::RemoveUser { certificate, .. }
+            | Event::MoveUser { certificate, .. }
+            | Event::SetPolicy { certificate, .. }
+            | Event::FileAppeal { certificate, .. }
+            | Event::ReviewAppeal { certificate, .. } => certificate,
+            Event::RedeemInvitation { invitation, .. } => &invitation.invitation.certificate,
+        }
+    }
+
+    /// The user whose signature authorizes the event, if it needs one.
+    pub fn actor(&self) -> Option<&str> {
+        match self {
+            Event::AddUserTree { .. } | Event::SetPolicy { .. } => Some(ADMIN_ID),
+            Event::AddUser { parent, .. } => Some(parent),
+            Event::RedeemInvitation { user_id, .. } => Some(user_id),
+            Event::ReportUser { reporter, .. } => Some(reporter),
+            Event::BlockUser { blocker, .. } => Some(blocker),
+            Event::UnblockUser { unblocker, .. } => Some(unblocker),
+            Event::RemoveUser { remover, .. } => Some(remover),
+            Event::MoveUser { mover, .. } => Some(mover),
+            Event::FileAppeal { user_id, .. } => Some(user_id),
+            Event::ReviewAppeal { reviewer, .. } => Some(reviewer),
+        }
+    }
+
+    /// The message the actor signs to authorize the event as its nonce-th
+    /// operation.
+    pub fn signing_bytes(&self, nonce: u64) -> Vec<u8> {
+        let mut bytes = SIGNING_DOMAIN.to_vec();
+        bytes.extend_from_slice(&nonce.to_be_bytes());
+        bytes.extend(serde_json::to_vec(self).expect("events serialize to JSON"));
+        bytes
+    }
+}
+
+/// An actor's signature over an event, bound to the actor's nonce so that it
+/// can not be replayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Authorization {
+    pub nonce: u64,
+    pub signature: Signature,
+}
+
+impl Authorization {
+    pub fn sign(event: &Event, nonce: u64, key: &SecretKey) -> Authorization {
+        Authorization {
+            nonce,
+            signature: key.sign(&event.signing_bytes(nonce)),
+        }
+    }
+}
+
+/// Another user's consent to an event, signed over the same message as the
+/// actor's authorization and so bound to the actor's nonce.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Approval {
+    pub user_id: String,
+    pub signature: Signature,
+}
+
+impl Approval {
+    pub fn sign(event: &Event, nonce: u64, user_id: &str, key: &SecretKey) -> Approval {
+        Approval {
+            user_id: user_id.to_owned(),
+            signature: key.sign(&event.signing_bytes(nonce)),
+        }
+    }
+}
+
+/// An event together with the authorization it was applied with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedEvent {
+    #[serde(flatten)]
+    pub event: Event,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub authorization: Option<Authorization>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub approvals: Vec<Approval>,
+    /// Unix time the event was applied at, stamped by the database applying
+    /// it and not covered by the signature.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub applied_at: Option<u64>,
+}
+
+/// Append-only log of the events applied to a database.
+pub trait EventLog {
+    /// Appends an event, returning its sequence number.
+    fn append(&mut self, event: &SignedEvent) -> Result<u64>;
+
+    /// Appends the events of a transaction. Durable logs write them at once,
+    /// so that a failure does not keep only some of them.
+    fn append_all(&mut self, events: &[SignedEvent]) -> Result<()> {
+        for event in events {
+            self.append(event)?;
+        }
+
+        Ok(())
+    }
+
+    /// All events in the order they were appended.
+    fn events(&self) -> Result<Vec<SignedEvent>>;
+
+    fn len(&self) -> u64;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Process-local event log.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryEventLog {
+    events: Vec<SignedEvent>,
+}
+
+impl MemoryEventLog {
+    pub fn new() -> MemoryEventLog {
+        MemoryEventLog::default()
+    }
+}
+
+impl EventLog for MemoryEventLog {
+    fn append(&mut self, event: &SignedEvent) -> Result<u64> {
+        self.events.push(event.clone());
+        Ok(self.events.len() as u64 - 1)
+    }
+
+    fn events(&self) -> Result<Vec<SignedEvent>> {
+        Ok(self.events.clone())
+    }
+
+    fn len(&self) -> u64 {
+        self.events.len() as u64
+    }
+}
+
+/// Event log stored as one JSON event per line, synced on every append.
+#[derive(Debug)]
+pub struct FileEventLog {
+    path: PathBuf,
+    file: File,
+    len: u64,
+}
+
+impl FileEventLog {
+    /// Opens the log file at path for appending, creating it if needed.
+    pub fn open(path: impl AsRef<Path>) -> Result<FileEventLog> {
+        let path = path.as_ref().to_path_buf();
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|error| log_error(&path, error))?;
+
+        let mut log = FileEventLog { path, file, len: 0 };
+        log.len = log.events()?.len() as u64;
+
+        Ok(log)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl EventLog for FileEventLog {
+    fn append(&mut self, event: &SignedEvent) -> Result<u64> {
+        let mut line = serde_json::to_vec(event).map_err(|error| log_error(&self.path, error))?;
+        line.push(b'\n');
+
+        self.file
+            .write_all(&line)
+            .map_err(|error| log_error(&self.path, error))?;
+        self.file.sync_data().map_err(|error| log_error(&self.path, error))?;
+
+        self.len += 1;
+
+        Ok(self.len - 1)
+    }
+
+    fn append_all(&mut self, events: &[SignedEvent]) -> Result<()> {
+        let mut lines = Vec::new();
+
+        for event in events {
+            serde_json::to_writer(&mut lines, event).map_err(|error| log_error(&self.path, error))?;
+            lines.push(b'\n');
+        }
+
+        self.file
+            .write_all(&lines)
+            .map_err(|error| log_error(&self.path, error))?;
+        self.file.sync_data().map_err(|error| log_error(&self.path, error))?;
+
+        self.len += events.len() as u64;
+
+        Ok(())
+    }
+
+    fn events(&self) -> Result<Vec<SignedEvent>> {
+        let file = match fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(log_error(&self.path, error)),
+        };
+
+        let mut events = Vec::new();
+
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(|error| log_error(&self.path, error))?;
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            events.push(serde_json::from_str(&line).map_err(|error| log_error(&self.path, error))?);
+        }
+
+        Ok(events)
+    }
+
+    fn len(&self) -> u64 {
+        self.len
+    }
+}
+
+fn log_error(path: &Path, error: impl std::fmt::Display) -> Error {
+    Error::Storage {
+        path: path.display().to_string(),
+        message: error.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::database::Database;
+    use crate::storage::MemoryStorage;
+    use crate::testing::{Fixture, CERTIFICATE};
+
+    fn report(reporter: &str) -> Event {
+        Event::ReportUser {
+            certificate: CERTIFICATE.to_owned(),
+            user_id: "bob".to_owned(),
+            reporter: reporter.to_owned(),
+            reason: ReportReason::Spam,
+            text: None,
+        }
+    }
+
+    fn signed(event: Event, authorization: Option<Authorization>) -> SignedEvent {
+        SignedEvent {
+            event,
+            authorization,
+            approvals: Vec::new(),
+            applied_at: None,
+        }
+    }
+
+    #[test]
+    fn events_need_the_actors_signature() {
+        let fixture = Fixture::with_users(&[("alice", ADMIN_ID), ("bob", ADMIN_ID)]);
+        let event = report("alice");
+
+        let unsigned = fixture.database.submit(signed(event.clone(), None));
+        assert!(matches!(unsigned, Err(Error::InvalidSignature { user_id }) if user_id == "alice"));
+
+        // Signed by bob's key in alice's name
+        let authorization = fixture.database.authorize(&event, fixture.key("bob"));
+        let forged = fixture.database.submit(signed(event.clone(), Some(authorization)));
+        assert!(matches!(forged, Err(Error::InvalidSignature { user_id }) if user_id == "alice"));
+
+        // Signed for another event
+        let authorization = fixture.database.authorize(&report("bob"), fixture.key("alice"));
+        let moved = fixture.database.submit(signed(event, Some(authorization)));
+        assert!(matches!(moved, Err(Error::InvalidSignature { .. })));
+
+        assert_eq!(fixture.database.nonce("alice", CERTIFICATE).unwrap(), 0);
+    }
+
+    #[test]
+    fn signed_events_can_not_be_replayed() {
+        let fixture = Fixture::with_users(&[("alice", ADMIN_ID), ("bob", ADMIN_ID)]);
+        let event = report("alice");
+        let authorization = fixture.database.authorize(&event, fixture.key("alice"));
+
+        fixture
+            .database
+            .submit(signed(event.clone(), Some(authorization)))
+            .unwrap();
+        assert_eq!(fixture.database.nonce("alice", CERTIFICATE).unwrap(), 1);
+
+        let replayed = fixture.database.submit(signed(event, Some(authorization)));
+        assert!(matches!(
+            replayed,
+            Err(Error::InvalidNonce {
+                expected: 1,
+                found: 0,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn failed_events_do_not_consume_the_nonce() {
+        let fixture = Fixture::with_users(&[("alice", ADMIN_ID)]);
+
+        assert!(fixture.submit(report("alice")).is_err(), "bob does not exist");
+        assert_eq!(fixture.database.nonce("alice", CERTIFICATE).unwrap(), 0);
+    }
+
+    #[test]
+    fn replaying_the_log_rebuilds_the_trees() {
+        let mut fixture = Fixture::with_users(&[("alice", ADMIN_ID), ("bob", "alice")]);
+        fixture.clock.advance(60);
+        fixture.block("bob", "alice", Some(3600)).unwrap();
+        fixture.clock.advance(60);
+        fixture.add("carol", "alice", Role::Member).unwrap();
+
+        // Failing events are not logged
+        let logged = fixture.database.log().len();
+        assert!(fixture.block("dave", "alice", None).is_err());
+        assert_eq!(fixture.database.log().len(), logged);
+
+        let log = fixture.database.log().clone();
+        let replayed = Database::replay(MemoryStorage::new(), log)
+            .unwrap()
+            .with_clock(Arc::clone(&fixture.clock));
+
+        assert_eq!(
+            replayed.tree_root(CERTIFICATE).unwrap(),
+            fixture.database.tree_root(CERTIFICATE).unwrap()
+        );
+        assert_eq!(
+            replayed.user_info("bob", CERTIFICATE).unwrap().user,
+            fixture.database.user_info("bob", CERTIFICATE).unwrap().user
+        );
+    }
+}
@@ -0,0 +1,363 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::appeal::Appeal;
+use crate::moderation::AuditEntry;
+use crate::policy::Policy;
+use crate::user::{User, ADMIN_ID};
+
+/// A user tree the way it is stored, which is checked for the invariants the
+/// tree relies on before it is loaded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredTree {
+    pub certificate: String,
+    pub users: BTreeMap<String, User>,
+    /// Nonces of the invitations redeemed so far.
+    #[serde(default)]
+    pub redeemed_invitations: BTreeSet<String>,
+    /// Next nonces of removed users.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub removed: BTreeMap<String, u64>,
+    #[serde(default, skip_serializing_if = "Policy::is_default")]
+    pub policy: Policy,
+    /// Actions the policy's rules took by themselves.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub audit: Vec<AuditEntry>,
+    /// Appeals against blocks, indexed by their ids.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub appeals: Vec<Appeal>,
+    /// User ids in the order of their leaves in the commitment.
+    #[serde(default)]
+    pub leaves: Vec<String>,
+    /// Length of the event log once the tree's last event was appended.
+    #[serde(default)]
+    pub logged: u64,
+}
+
+/// A broken invariant of a stored user tree.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "violation", rename_all = "snake_case")]
+pub enum Violation {
+    /// No user has the admin's id.
+    MissingAdmin,
+    /// A user is stored under another id than its own.
+    MismatchedId { user_id: String, id: String },
+    /// The admin has another user as its parent.
+    AdminHasParent { parent: String },
+    /// A user other than the admin is its own parent.
+    ExtraRoot { user_id: String },
+    /// A user's parent does not exist.
+    DanglingParent { user_id: String, parent: String },
+    /// A user is not among its parent's children.
+    MissingChild { user_id: String, parent: String },
+    /// A user lists a child that does not exist.
+    DanglingChild { user_id: String, child: String },
+    /// A user lists a child whose parent is another user.
+    ForeignChild { user_id: String, child: String },
+    /// A user lists the same child more than once.
+    DuplicateChild { user_id: String, child: String },
+    /// The parents of these users loop without reaching the admin.
+    Cycle { user_ids: Vec<String> },
+}
+
+impl Violation {
+    /// Whether [`StoredTree::repair`] fixes the violation. Nothing can be
+    /// repaired without an admin to move orphaned users under.
+    pub fn is_repairable(&self) -> bool {
+        *self != Violation::MissingAdmin
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::MissingAdmin => write!(f, "no user '{ADMIN_ID}'"),
+            Violation::MismatchedId { user_id, id } => write!(f, "user '{user_id}' is stored with id '{id}'"),
+            Violation::AdminHasParent { parent } => write!(f, "admin has parent '{parent}'"),
+            Violation::ExtraRoot { user_id } => write!(f, "user '{user_id}' is its own parent but not the admin"),
+            Violation::DanglingParent { user_id, parent } => {
+                write!(f, "parent '{parent}' of user '{user_id}' does not exist")
+            }
+            Violation::MissingChild { user_id, parent } => {
+                write!(f, "user '{user_id}' is not a child of its parent '{parent}'")
+            }
+            Violation::DanglingChild { user_id, child } => {
+                write!(f, "child '{child}' of user '{user_id}' does not exist")
+            }
+            Violation::ForeignChild { user_id, child } => {
+                write!(
+                    f,
+                    "user '{user_id}' lists '{child}' as a child, which has another parent"
+                )
+            }
+            Violation::DuplicateChild { user_id, child } => {
+                write!(f, "user '{user_id}' lists child '{child}' more than once")
+            }
+            Violation::Cycle { user_ids } => write!(f, "parents of users {user_ids:?} loop without reaching the admin"),
+        }
+    }
+}
+
+/// Violations found in, and possibly repaired in, a certificate's tree.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TreeReport {
+    pub certificate: String,
+    pub violations: Vec<Violation>,
+    /// Whether the repairable violations were repaired.
+    pub repaired: bool,
+}
+
+impl StoredTree {
+    /// Every violation of the tree's invariants.
+    pub fn check(&self) -> Vec<Violation> {
+        let mut violations = Vec::new();
+
+        if !self.users.contains_key(ADMIN_ID) {
+            violations.push(Violation::MissingAdmin);
+        }
+
+        for (user_id, user) in self.users.iter() {
+            if user.id != *user_id {
+                violations.push(Violation::MismatchedId {
+                    user_id: user_id.clone(),
+                    id: user.id.clone(),
+                });
+            }
+
+            let parent = &user.parent;
+
+            if user_id == ADMIN_ID {
+                if parent != ADMIN_ID {
+                    violations.push(Violation::AdminHasParent { parent: parent.clone() });
+                }
+            } else if parent == user_id {
+                violations.push(Violation::ExtraRoot {
+                    user_id: user_id.clone(),
+                });
+            } else {
+                match self.users.get(parent) {
+                    None => violations.push(Violation::DanglingParent {
+                        user_id: user_id.clone(),
+                        parent: parent.clone(),
+                    }),
+                    Some(parent_user) if !parent_user.children.contains(user_id) => {
+                        violations.push(Violation::MissingChild {
+                            user_id: user_id.clone(),
+                            parent: parent.clone(),
+                        })
+                    }
+                    Some(_) => {}
+                }
+            }
+
+            let mut seen = BTreeSet::new();
+
+            for child in user.children.iter() {
+                let (user_id, child) = (user_id.clone(), child.clone());
+
+                if !seen.insert(child.clone()) {
+                    violations.push(Violation::DuplicateChild { user_id, child });
+                    continue;
+                }
+
+                match self.users.get(&child) {
+                    None => violations.push(Violation::DanglingChild { user_id, child }),
+                    Some(child_user) if child_user.parent != user_id || child == user_id => {
+                        violations.push(Violation::ForeignChild { user_id, child })
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+
+        violations.extend(self.cycles().into_iter().map(|user_ids| Violation::Cycle { user_ids }));
+
+        violations
+    }
+
+    /// Fixes every repairable violation, returning the violations found
+    /// before. Users without a valid parent, and the user with the smallest
+    /// id of each cycle, are moved under the admin and blocked, so that the
+    /// repair grants no one permission.
+    pub fn repair(&mut self) -> Vec<Violation> {
+        let violations = self.check();
+
+        if !self.users.contains_key(ADMIN_ID) {
+            return violations;
+        }
+
+        // Ids
+        for (user_id, user) in self.users.iter_mut() {
+            user.id = user_id.clone();
+        }
+
+        self.user_mut(ADMIN_ID).parent = ADMIN_ID.to_owned();
+
+        // Children, once each and only those whose parent is the user
+        let parents: HashMap<String, String> = self
+            .users
+            .iter()
+            .map(|(user_id, user)| (user_id.clone(), user.parent.clone()))
+            .collect();
+
+        for (user_id, user) in self.users.iter_mut() {
+            let mut seen = BTreeSet::new();
+
+            user.children
+                .retain(|child| child != user_id && parents.get(child) == Some(user_id) && seen.insert(child.clone()));
+        }
+
+        // Parents
+        let user_ids: Vec<String> = self.users.keys().cloned().collect();
+
+        for user_id in user_ids.iter().filter(|user_id| *user_id != ADMIN_ID) {
+            let parent = self.users[user_id].parent.clone();
+
+            if parent == *user_id || !self.users.contains_key(&parent) {
+                self.adopt(user_id);
+            } else if !self.users[&parent].children.contains(user_id) {
+                self.user_mut(&parent).children.push(user_id.clone());
+            }
+        }
+
+        // Cycles
+        for cycle in self.cycles() {
+            let user_id = &cycle[0];
+            let parent = self.users[user_id].parent.clone();
+
+            self.user_mut(&parent).children.retain(|child| child != user_id);
+            self.adopt(user_id);
+        }
+
+        violations
+    }
+
+    /// Moves a user under the admin, blocked for good.
+    fn adopt(&mut self, user_id: &str) {
+        let user = self.user_mut(user_id);
+        user.parent = ADMIN_ID.to_owned();
+        user.blocked = true;
+        user.blocked_until = None;
+
+        self.user_mut(ADMIN_ID).children.push(user_id.to_owned());
+    }
+
+    fn user_mut(&mut self, user_id: &str) -> &mut User {
+        self.users.get_mut(user_id).expect("repaired users exist")
+    }
+
+    /// Users whose parents loop without reaching the admin, sorted, one group
+    /// per loop.
+    fn cycles(&self) -> Vec<Vec<String>> {
+        // Index of the walk that first reached each user
+        let mut reached: HashMap<&str, usize> = HashMap::new();
+        let mut cycles = Vec::new();
+
+        for (walk, user_id) in self.users.keys().enumerate() {
+            let mut path: Vec<&str> = Vec::new();
+            let mut current = user_id.as_str();
+
+            loop {
+                if let Some(&earlier) = reached.get(current) {
+                    if earlier == walk {
+                        let start = path
+                            .iter()
+                            .position(|user_id| *user_id == current)
+                            .expect("reached on this walk");
+                        let mut cycle: Vec<String> = path[start..].iter().map(|user_id| user_id.to_string()).collect();
+                        cycle.sort();
+                        cycles.push(cycle);
+                    }
+
+                    break;
+                }
+
+                reached.insert(current, walk);
+                path.push(current);
+
+                // Walks end at a root or a dangling parent
+                match self.users.get(current) {
+                    Some(user) if user.parent != current => current = &user.parent,
+                    _ => break,
+                }
+            }
+        }
+
+        cycles
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{Fixture, CERTIFICATE};
+    use crate::tree::UserTree;
+
+    fn stored(fixture: &Fixture) -> StoredTree {
+        let json = fixture
+            .database
+            .read_tree(CERTIFICATE, |tree| serde_json::to_string(tree).unwrap())
+            .unwrap();
+
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn repairs_block_the_users_they_move() {
+        let fixture = Fixture::with_users(&[
+            ("alice", ADMIN_ID),
+            ("bob", "alice"),
+            ("dave", "bob"),
+            ("erin", "alice"),
+            ("frank", "erin"),
+        ]);
+        let mut tree = stored(&fixture);
+        assert_eq!(tree.check(), []);
+
+        tree.users.get_mut("dave").unwrap().parent = "ghost".to_owned();
+        tree.users.get_mut("erin").unwrap().parent = "frank".to_owned();
+        tree.users.get_mut("frank").unwrap().children.push("erin".to_owned());
+        tree.users.get_mut("alice").unwrap().children.push("bob".to_owned());
+
+        let violations = tree.repair();
+        for violation in [
+            Violation::DanglingParent {
+                user_id: "dave".to_owned(),
+                parent: "ghost".to_owned(),
+            },
+            Violation::DuplicateChild {
+                user_id: "alice".to_owned(),
+                child: "bob".to_owned(),
+            },
+            Violation::Cycle {
+                user_ids: vec!["erin".to_owned(), "frank".to_owned()],
+            },
+        ] {
+            assert!(violations.contains(&violation), "{violation}");
+        }
+
+        assert_eq!(tree.check(), []);
+        assert_eq!(tree.users["alice"].children, ["bob"]);
+        assert_eq!(tree.users["frank"].parent, "erin");
+
+        let tree = UserTree::try_from(tree).unwrap();
+        for user_id in ["dave", "erin", "frank"] {
+            assert!(!tree.check_user_permission(user_id).unwrap().is_granted(), "{user_id}");
+        }
+        assert_eq!(tree.get("erin").unwrap().parent, ADMIN_ID);
+        assert!(tree.check_user_permission("bob").unwrap().is_granted());
+    }
+
+    #[test]
+    fn trees_without_an_admin_are_left_alone() {
+        let mut tree = stored(&Fixture::with_users(&[("alice", ADMIN_ID)]));
+        tree.users.remove(ADMIN_ID);
+        let before = serde_json::to_value(&tree).unwrap();
+
+        assert!(tree.repair().contains(&Violation::MissingAdmin));
+        assert!(!Violation::MissingAdmin.is_repairable());
+        assert_eq!(serde_json::to_value(&tree).unwrap(), before);
+    }
+}
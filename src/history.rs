@@ -0,0 +1,136 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::names::names;
+use crate::report::ReportReason;
+
+/// Reason code of a block or unblock, starting with the reasons of
+/// [`ReportReason`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BlockReason {
+    Spam,
+    Abuse,
+    Impersonation,
+    Fraud,
+    /// A policy rule acted by itself.
+    Rule,
+    /// An appeal against the block was accepted.
+    Appeal,
+    /// The issue behind the block was resolved.
+    Resolved,
+    /// The reason of blocks made before reasons were recorded.
+    #[default]
+    Other,
+}
+
+impl BlockReason {
+    pub fn is_default(&self) -> bool {
+        *self == BlockReason::default()
+    }
+}
+
+names! {
+    /// Error parsing a block reason code.
+    BlockReason, ParseBlockReasonError {
+        Spam => "spam",
+        Abuse => "abuse",
+        Impersonation => "impersonation",
+        Fraud => "fraud",
+        Rule => "rule",
+        Appeal => "appeal",
+        Resolved => "resolved",
+        Other => "other",
+    }
+}
+
+/// A report's reason as the reason of the block it led to.
+impl From<ReportReason> for BlockReason {
+    fn from(reason: ReportReason) -> BlockReason {
+        match reason {
+            ReportReason::Spam => BlockReason::Spam,
+            ReportReason::Abuse => BlockReason::Abuse,
+            ReportReason::Impersonation => BlockReason::Impersonation,
+            ReportReason::Fraud => BlockReason::Fraud,
+            ReportReason::Other => BlockReason::Other,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BlockAction {
+    Block,
+    Unblock,
+}
+
+impl fmt::Display for BlockAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockAction::Block => f.write_str("blocked"),
+            BlockAction::Unblock => f.write_str("unblocked"),
+        }
+    }
+}
+
+/// A block or unblock of a user, kept in the user's history.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockRecord {
+    pub action: BlockAction,
+    /// None when a policy rule acted by itself.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actor: Option<String>,
+    pub reason: BlockReason,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// Unix time the block or unblock was applied at.
+    pub time: u64,
+    /// Unix time a temporary block ends at.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<u64>,
+}
+
+impl fmt::Display for BlockRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.action)?;
+
+        match &self.actor {
+            Some(actor) => write!(f, " by {actor} for {}", self.reason)?,
+            None => f.write_str(" by a rule")?,
+        }
+
+        write!(f, " at {}", self.time)?;
+
+        if let Some(until) = self.until {
+            write!(f, " until {until}")?;
+        }
+
+        match &self.text {
+            Some(text) => write!(f, ": {text}"),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reason_names_are_their_serde_names() {
+        for reason in BlockReason::ALL {
+            assert_eq!(serde_json::to_value(reason).unwrap(), reason.to_string());
+            assert_eq!(reason.to_string().parse(), Ok(reason));
+        }
+
+        for reason in ReportReason::ALL {
+            assert_eq!(BlockReason::from(reason).to_string(), reason.to_string());
+        }
+
+        assert_eq!(
+            "banned".parse::<BlockReason>().unwrap_err().to_string(),
+            "expected one of spam, abuse, impersonation, fraud, rule, appeal, resolved or other"
+        );
+    }
+}
@@ -0,0 +1,198 @@
+use std::fmt;
+use std::str::FromStr;
+
+use ed25519_dalek::{Signer, SigningKey, Verifier, VerifyingKey};
+use rand_core::OsRng;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Ed25519 public key identifying a user, written as hex.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PublicKey([u8; 32]);
+
+/// Ed25519 signature, written as hex.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Signature([u8; 64]);
+
+/// Ed25519 secret key a user signs its operations with.
+#[derive(Clone)]
+pub struct SecretKey(SigningKey);
+
+impl PublicKey {
+    pub fn from_bytes(bytes: [u8; 32]) -> PublicKey {
+        PublicKey(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Checks that signature was made over message by the secret key of this
+    /// public key.
+    pub fn verify(&self, message: &[u8], signature: &Signature) -> bool {
+        match VerifyingKey::from_bytes(&self.0) {
+            Ok(key) => key
+                .verify(message, &ed25519_dalek::Signature::from_bytes(&signature.0))
+                .is_ok(),
+            Err(_) => false,
+        }
+    }
+}
+
+impl Signature {
+    pub fn from_bytes(bytes: [u8; 64]) -> Signature {
+        Signature(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 64] {
+        &self.0
+    }
+}
+
+impl SecretKey {
+    /// Generates a new random secret key.
+    pub fn generate() -> SecretKey {
+        SecretKey(SigningKey::generate(&mut OsRng))
+    }
+
+    pub fn from_bytes(bytes: [u8; 32]) -> SecretKey {
+        SecretKey(SigningKey::from_bytes(&bytes))
+    }
+
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0.to_bytes()
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey(self.0.verifying_key().to_bytes())
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Signature {
+        Signature(self.0.sign(message).to_bytes())
+    }
+}
+
+/// Error parsing a hex encoded key or signature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseKeyError {
+    expected_bytes: usize,
+}
+
+impl fmt::Display for ParseKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected {} hex encoded bytes", self.expected_bytes)
+    }
+}
+
+impl std::error::Error for ParseKeyError {}
+
+pub(crate) fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+pub(crate) fn decode_hex<const N: usize>(text: &str) -> Result<[u8; N], ParseKeyError> {
+    let error = ParseKeyError { expected_bytes: N };
+    let text = text.trim();
+
+    if text.len() != N * 2 || !text.is_ascii() {
+        return Err(error);
+    }
+
+    let mut bytes = [0; N];
+
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&text[i * 2..i * 2 + 2], 16).map_err(|_| error.clone())?;
+    }
+
+    Ok(bytes)
+}
+
+/// Implements hex `Display`, `FromStr` and serde for a fixed-size byte type.
+macro_rules! hex_encoding {
+    ($type:ident, $from:expr, $bytes:expr) => {
+        impl fmt::Display for $type {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(&encode_hex(&$bytes(self)))
+            }
+        }
+
+        impl fmt::Debug for $type {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}({})", stringify!($type), self)
+            }
+        }
+
+        impl FromStr for $type {
+            type Err = ParseKeyError;
+
+            fn from_str(text: &str) -> Result<$type, ParseKeyError> {
+                decode_hex(text).map($from)
+            }
+        }
+
+        impl Serialize for $type {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(&self.to_string())
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $type {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<$type, D::Error> {
+                let text = String::deserialize(deserializer)?;
+                text.parse().map_err(serde::de::Error::custom)
+            }
+        }
+    };
+}
+
+pub(crate) use hex_encoding;
+
+hex_encoding!(PublicKey, PublicKey::from_bytes, |key: &PublicKey| key.0);
+hex_encoding!(Signature, Signature::from_bytes, |signature: &Signature| signature.0);
+
+impl fmt::Display for SecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&encode_hex(&self.to_bytes()))
+    }
+}
+
+impl fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SecretKey({})", self.public_key())
+    }
+}
+
+impl FromStr for SecretKey {
+    type Err = ParseKeyError;
+
+    fn from_str(text: &str) -> Result<SecretKey, ParseKeyError> {
+        decode_hex(text).map(SecretKey::from_bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signatures_verify_only_for_their_key_and_message() {
+        let key = SecretKey::generate();
+        let signature = key.sign(b"block bob");
+
+        assert!(key.public_key().verify(b"block bob", &signature));
+        assert!(!key.public_key().verify(b"block carol", &signature));
+        assert!(!SecretKey::generate().public_key().verify(b"block bob", &signature));
+    }
+
+    #[test]
+    fn keys_round_trip_through_hex() {
+        let key = SecretKey::generate();
+
+        let secret: SecretKey = key.to_string().parse().unwrap();
+        let public: PublicKey = key.public_key().to_string().parse().unwrap();
+
+        assert_eq!(secret.public_key(), key.public_key());
+        assert_eq!(public, key.public_key());
+        assert!("abc".parse::<PublicKey>().is_err());
+        assert!("zz".repeat(32).parse::<PublicKey>().is_err());
+    }
+}
@@ -0,0 +1,75 @@
+use serde::Serialize;
+
+use crate::merkle::Digest;
+use crate::policy::Policy;
+use crate::report::ReportScore;
+use crate::statistics::Statistics;
+use crate::tree::UserTree;
+use crate::user::User;
+
+/// A user together with the certificate tree it belongs to.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UserInfo {
+    pub certificate: String,
+    #[serde(flatten)]
+    pub user: User,
+    /// Report score at the time the information was read.
+    pub score: ReportScore,
+}
+
+/// Size and shape of a certificate tree.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TreeInfo {
+    pub certificate: String,
+    pub users: usize,
+    pub blocked: usize,
+    /// Average number of children, included childless users.
+    pub average_children: f32,
+    /// Average number of children, excluded childless users.
+    pub average_children_excluded_childless: f32,
+    /// Merkle root committing to every user of the tree.
+    pub root: Digest,
+    pub policy: Policy,
+    pub statistics: Statistics,
+}
+
+impl TreeInfo {
+    /// Information about a tree at the Unix time now.
+    pub fn new(tree: &UserTree, now: u64) -> TreeInfo {
+        let mut average: f32 = 0.0;
+
+        let mut average_excluded_childless: f32 = 0.0;
+        let mut n: u32 = 0;
+        let mut blocked = 0;
+
+        for user in tree.users() {
+            average += user.children.len() as f32;
+
+            if !user.children.is_empty() {
+                n += 1;
+                average_excluded_childless += user.children.len() as f32;
+            }
+
+            if user.is_blocked_at(now) {
+                blocked += 1;
+            }
+        }
+
+        average /= tree.len() as f32;
+
+        if n > 0 {
+            average_excluded_childless /= n as f32;
+        }
+
+        TreeInfo {
+            certificate: tree.certificate().to_owned(),
+            users: tree.len(),
+            blocked,
+            average_children: average,
+            average_children_excluded_childless: average_excluded_childless,
+            root: tree.root(),
+            policy: tree.policy().clone(),
+            statistics: tree.statistics(),
+        }
+    }
+}
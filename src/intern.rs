@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Compact handle of an interned user id, also its index in a tree's arena.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct Handle(u32);
+
+impl Handle {
+    pub(crate) fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Stores every user id of a tree once, handing out handles in the order ids
+/// were first seen.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Interner {
+    ids: Vec<Arc<str>>,
+    handles: HashMap<Arc<str>, Handle>,
+}
+
+impl Interner {
+    /// Returns the handle of id, interning it if it is new.
+    pub(crate) fn intern(&mut self, id: &str) -> Handle {
+        if let Some(handle) = self.handles.get(id) {
+            return *handle;
+        }
+
+        let handle = Handle(u32::try_from(self.ids.len()).expect("fewer than 2^32 users per tree"));
+        let id: Arc<str> = Arc::from(id);
+
+        self.ids.push(id.clone());
+        self.handles.insert(id, handle);
+
+        handle
+    }
+
+    /// Forgets the id of handle, moving the last id into its place. Returns
+    /// the handle the moved id had, if an id was moved.
+    pub(crate) fn swap_remove(&mut self, handle: Handle) -> Option<Handle> {
+        let id = self.ids.swap_remove(handle.index());
+        self.handles.remove(&id);
+
+        let moved = self.ids.get(handle.index())?;
+        self.handles.insert(moved.clone(), handle);
+
+        Some(Handle(self.ids.len() as u32))
+    }
+
+    /// Forgets the id interned last.
+    pub(crate) fn pop(&mut self) {
+        if let Some(id) = self.ids.pop() {
+            self.handles.remove(&id);
+        }
+    }
+
+    /// Puts back the id a swap removal forgot, moving the id in its place
+    /// back to the end.
+    pub(crate) fn restore(&mut self, handle: Handle, id: &str) {
+        let id: Arc<str> = Arc::from(id);
+        let last = Handle(self.ids.len() as u32);
+
+        match self.ids.get_mut(handle.index()) {
+            Some(slot) => {
+                let moved = std::mem::replace(slot, id.clone());
+                self.handles.insert(moved.clone(), last);
+                self.ids.push(moved);
+            }
+            None => self.ids.push(id.clone()),
+        }
+
+        self.handles.insert(id, handle);
+    }
+
+    pub(crate) fn get(&self, id: &str) -> Option<Handle> {
+        self.handles.get(id).copied()
+    }
+
+    pub(crate) fn resolve(&self, handle: Handle) -> &str {
+        &self.ids[handle.index()]
+    }
+
+    pub(crate) fn handles(&self) -> impl Iterator<Item = Handle> {
+        (0..self.ids.len() as u32).map(Handle)
+    }
+}
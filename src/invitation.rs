@@ -0,0 +1,208 @@
+use std::fmt;
+use std::str::FromStr;
+
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+
+use crate::delegation::Limits;
+use crate::identity::{PublicKey, SecretKey, Signature};
+use crate::role::Role;
+
+/// Prefix of every signed invitation, so that it can not be confused with a
+/// signed event.
+const SIGNING_DOMAIN: &[u8] = b"tree-structured-org/invitation/v1\n";
+
+/// A parent's offer to add a child under it in a certificate tree.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Invitation {
+    pub certificate: String,
+    pub parent: String,
+    /// The only user id the invitation can be redeemed for, if any.
+    pub user_id: Option<String>,
+    /// Role the redeeming user is granted.
+    #[serde(default = "Role::legacy", skip_serializing_if = "Role::is_legacy")]
+    pub role: Role,
+    /// Limits placed on the redeeming user's subtree.
+    #[serde(default, skip_serializing_if = "Limits::is_none")]
+    pub limits: Limits,
+    /// Unix time in seconds after which the invitation can not be redeemed.
+    pub expires_at: u64,
+    /// Random value making the invitation single-use.
+    pub nonce: String,
+}
+
+/// An invitation signed by the parent's key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedInvitation {
+    pub invitation: Invitation,
+    pub signature: Signature,
+}
+
+impl Invitation {
+    /// Creates an invitation with a fresh random nonce.
+    pub fn new(
+        certificate: &str,
+        parent: &str,
+        user_id: Option<&str>,
+        role: Role,
+        limits: Limits,
+        expires_at: u64,
+    ) -> Invitation {
+        let mut nonce = [0; 16];
+        OsRng.fill_bytes(&mut nonce);
+
+        Invitation {
+            certificate: certificate.to_owned(),
+            parent: parent.to_owned(),
+            user_id: user_id.map(str::to_owned),
+            role,
+            limits,
+            expires_at,
+            nonce: nonce.iter().map(|byte| format!("{byte:02x}")).collect(),
+        }
+    }
+
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let mut bytes = SIGNING_DOMAIN.to_vec();
+        bytes.extend(serde_json::to_vec(self).expect("invitations serialize to JSON"));
+        bytes
+    }
+
+    pub fn sign(self, key: &SecretKey) -> SignedInvitation {
+        let signature = key.sign(&self.signing_bytes());
+
+        SignedInvitation {
+            invitation: self,
+            signature,
+        }
+    }
+}
+
+impl SignedInvitation {
+    /// Checks the parent's signature over the invitation.
+    pub fn verify(&self, parent_key: &PublicKey) -> bool {
+        parent_key.verify(&self.invitation.signing_bytes(), &self.signature)
+    }
+}
+
+/// Invitations are handed out as a hex token of their JSON encoding.
+impl fmt::Display for SignedInvitation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let json = serde_json::to_vec(self).map_err(|_| fmt::Error)?;
+
+        for byte in json {
+            write!(f, "{byte:02x}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Error parsing an invitation token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseTokenError;
+
+impl fmt::Display for ParseTokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid invitation token")
+    }
+}
+
+impl std::error::Error for ParseTokenError {}
+
+impl FromStr for SignedInvitation {
+    type Err = ParseTokenError;
+
+    fn from_str(token: &str) -> Result<SignedInvitation, ParseTokenError> {
+        let token = token.trim();
+
+        if !token.len().is_multiple_of(2) || !token.is_ascii() {
+            return Err(ParseTokenError);
+        }
+
+        let json = (0..token.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&token[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| ParseTokenError)?;
+
+        serde_json::from_slice(&json).map_err(|_| ParseTokenError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::event::{Event, SignedEvent};
+    use crate::testing::{Fixture, CERTIFICATE};
+    use crate::user::ADMIN_ID;
+
+    fn invite(fixture: &Fixture, user_id: Option<&str>, signer: &str) -> SignedInvitation {
+        let expires_at = fixture.database.now() + 60;
+        Invitation::new(CERTIFICATE, "alice", user_id, Role::Member, Limits::NONE, expires_at).sign(fixture.key(signer))
+    }
+
+    fn redeem(fixture: &Fixture, invitation: &SignedInvitation, user_id: &str) -> crate::error::Result<()> {
+        let key = SecretKey::generate();
+        let event = Event::RedeemInvitation {
+            user_id: user_id.to_owned(),
+            public_key: key.public_key(),
+            invitation: invitation.clone(),
+        };
+        let authorization = fixture.database.authorize(&event, &key);
+
+        fixture
+            .database
+            .redeem_invitation(invitation.clone(), user_id, key.public_key(), authorization)
+            .map_err(|error| error.root_cause().clone())
+    }
+
+    #[test]
+    fn invitations_are_redeemed_once() {
+        let fixture = Fixture::with_users(&[("alice", ADMIN_ID)]);
+        let invitation = invite(&fixture, None, "alice");
+        assert_eq!(invitation.to_string().parse(), Ok(invitation.clone()));
+
+        redeem(&fixture, &invitation, "dave").unwrap();
+        let dave = fixture.database.user_info("dave", CERTIFICATE).unwrap().user;
+        assert_eq!((dave.parent.as_str(), dave.role), ("alice", Role::Member));
+
+        let again = redeem(&fixture, &invitation, "erin");
+        assert!(matches!(again, Err(Error::InvitationRedeemed { .. })));
+    }
+
+    #[test]
+    fn invitations_hold_only_for_their_user_signer_and_time() {
+        let fixture = Fixture::with_users(&[("alice", ADMIN_ID), ("bob", ADMIN_ID)]);
+
+        let named = invite(&fixture, Some("dave"), "alice");
+        let other = redeem(&fixture, &named, "erin");
+        assert!(matches!(other, Err(Error::InvalidInvitation { reason }) if reason == "issued for another user"));
+
+        let forged = redeem(&fixture, &invite(&fixture, None, "bob"), "erin");
+        assert!(matches!(forged, Err(Error::InvalidInvitation { reason }) if reason == "not signed by the parent"));
+
+        fixture.clock.advance(61);
+        let expired = redeem(&fixture, &named, "dave");
+        assert!(matches!(expired, Err(Error::InvitationExpired { .. })));
+
+        // Submitted events are held to the expiry too
+        let key = SecretKey::generate();
+        let event = Event::RedeemInvitation {
+            user_id: "dave".to_owned(),
+            public_key: key.public_key(),
+            invitation: named,
+        };
+        let authorization = fixture.database.authorize(&event, &key);
+        let submitted = fixture.database.submit(SignedEvent {
+            event,
+            authorization: Some(authorization),
+            approvals: Vec::new(),
+            applied_at: None,
+        });
+        assert!(matches!(submitted, Err(Error::InvitationExpired { .. })));
+        assert!(fixture.database.user_info("dave", CERTIFICATE).is_err());
+        assert!("not a token".parse::<SignedInvitation>().is_err());
+    }
+}
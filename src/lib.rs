@@ -0,0 +1,57 @@
+//! Hierarchical decentralized authorization based on certificate user trees.
+//!
+//! A [`Database`] holds one [`UserTree`] per certificate. Every tree is rooted
+//! at the [`ADMIN_ID`] user and a user has permission in a certificate only if
+//! neither it nor any of its ancestors is blocked.
+
+mod appeal;
+mod clock;
+mod database;
+mod delegation;
+mod error;
+mod event;
+mod fsck;
+mod history;
+mod identity;
+mod info;
+mod intern;
+mod invitation;
+mod merkle;
+mod moderation;
+mod names;
+mod policy;
+mod proof;
+mod report;
+mod role;
+mod server;
+mod statistics;
+mod storage;
+#[cfg(test)]
+mod testing;
+mod transaction;
+mod tree;
+mod user;
+
+pub use appeal::{Appeal, AppealState, ParseAppealStateError, Vote};
+pub use clock::{unix_time, Clock, ManualClock, SystemClock};
+pub use database::{print_statistics, print_user_info, print_user_tree_info, Database};
+pub use delegation::{Limits, Permission};
+pub use error::{Error, ErrorKind, Result};
+pub use event::{Approval, Authorization, Event, EventLog, FileEventLog, MemoryEventLog, SignedEvent};
+pub use fsck::{StoredTree, TreeReport, Violation};
+pub use history::{BlockAction, BlockReason, BlockRecord, ParseBlockReasonError};
+pub use identity::{ParseKeyError, PublicKey, SecretKey, Signature};
+pub use info::{TreeInfo, UserInfo};
+pub use invitation::{Invitation, ParseTokenError, SignedInvitation};
+pub use merkle::{CommittedUser, Digest, InclusionProof, ProofStep};
+pub use moderation::{AuditEntry, AutomaticAction, Rule};
+pub use policy::{AppealPolicy, MovePolicy, Policy, Propagation, ReportPolicy};
+pub use proof::PermissionProof;
+pub use report::{ParseReasonError, Report, ReportReason, ReportScore};
+pub use role::{Capabilities, Capability, ParseRoleError, Role};
+pub use server::{Response, Server, DEFAULT_ADDRESS};
+pub use statistics::Statistics;
+pub use storage::{FileStorage, MemoryStorage, Storage};
+pub use transaction::Transaction;
+pub use tree::{Orphans, UserTree};
+pub use user::{User, ADMIN_ID};
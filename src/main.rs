@@ -1,663 +1,1174 @@
-use std::collections::HashMap;
-
-#[derive(Debug, Default)]
-struct Statistics {
-    user_add: u32,
-    user_update: u32,
-    user_read: u32,
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::net::TcpListener;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::{CommandFactory, Parser, Subcommand};
+use serde::Deserialize;
+use serde_json::json;
+
+use tree_structured_org::{
+    print_statistics, print_user_info, print_user_tree_info, unix_time, AppealState, BlockReason, Database, Digest,
+    Error, ErrorKind, Event, FileEventLog, FileStorage, InclusionProof, Invitation, Limits, Orphans, ParseKeyError,
+    PermissionProof, Policy, PublicKey, ReportReason, Result, Role, SecretKey, Server, SignedInvitation, Statistics,
+    DEFAULT_ADDRESS,
+};
+
+/// The user has permission, or the command succeeded.
+const EXIT_OK: u8 = 0;
+/// `check` found that the user does not have permission.
+const EXIT_DENIED: u8 = 1;
+/// A certificate or user does not exist.
+const EXIT_NOT_FOUND: u8 = 3;
+/// The operation conflicts with the current state of the tree.
+const EXIT_CONFLICT: u8 = 4;
+/// The acting user is not allowed to perform the operation.
+const EXIT_UNAUTHORIZED: u8 = 5;
+/// The database or its event log could not be read or written.
+const EXIT_STORAGE: u8 = 6;
+/// `fsck` found violations that are left unrepaired.
+const EXIT_CORRUPT: u8 = 7;
+
+/// Database directory used without `--database`.
+const DEFAULT_DATABASE: &str = "tree-org";
+/// Database file earlier versions used without `--database`.
+const LEGACY_DATABASE: &str = "tree-org.json";
+
+/// Manages certificate user trees stored in a database directory.
+#[derive(Parser)]
+#[command(name = "tree-org", version)]
+struct Cli {
+    /// Database directory with a file per certificate, its event log is kept next to it with a `.log` suffix
+    #[arg(long, global = true, default_value = DEFAULT_DATABASE)]
+    database: PathBuf,
+
+    /// Print machine-readable JSON instead of text
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
 }
 
-/// Represents a user in the system.
-struct User {
-    id: String,
-    parent: String,
-    children: Vec<String>,
-    reports: u16,
-    blocked: bool,
+#[derive(Subcommand)]
+enum Command {
+    /// Manage signing keys
+    #[command(subcommand)]
+    Key(KeyCommand),
+
+    /// Manage certificate trees
+    #[command(subcommand)]
+    Tree(TreeCommand),
+
+    /// Manage users of a certificate tree
+    #[command(subcommand)]
+    User(UserCommand),
+
+    /// Invite users and redeem invitations
+    #[command(subcommand)]
+    Invite(InviteCommand),
+
+    /// Appeal blocks and review appeals
+    #[command(subcommand)]
+    Appeal(AppealCommand),
+
+    /// Export and verify offline permission proofs
+    #[command(subcommand)]
+    Proof(ProofCommand),
+
+    /// Publish tree root hashes and prove users are committed to them
+    #[command(subcommand)]
+    Merkle(MerkleCommand),
+
+    /// Check if a user has permission, exits with 1 if it does not
+    Check {
+        user_id: String,
+        #[arg(long)]
+        cert: String,
+    },
+
+    /// Show a user of a certificate tree
+    Info {
+        user_id: String,
+        #[arg(long)]
+        cert: String,
+    },
+
+    /// Show statistics of one or all certificate trees
+    Stats {
+        #[arg(long)]
+        cert: Option<String>,
+    },
+
+    /// Check every certificate tree of the database for broken invariants
+    Fsck {
+        /// Fix the violations that can be fixed and rewrite the files of the repaired trees
+        #[arg(long)]
+        repair: bool,
+    },
+
+    /// Serve the HTTP/JSON API over the database
+    Serve {
+        #[arg(long, default_value = DEFAULT_ADDRESS)]
+        bind: String,
+    },
 }
 
-impl User {
-    fn new(id: &str, parent: &str) -> User {
-        User {
-            id: id.to_owned(),
-            parent: parent.to_owned(),
-            children: Vec::new(),
-            reports: 0,
-            blocked: false,
-        }
-    }
+#[derive(Subcommand)]
+enum KeyCommand {
+    /// Generate a secret key and print its public key
+    Generate {
+        /// File to create for the secret key, readable by its owner only
+        #[arg(long)]
+        out: PathBuf,
+    },
 }
 
-fn build_database()-> HashMap<String, HashMap<String, User>> {
-
-    let database: HashMap<String, HashMap<String, User>> = HashMap::new();
-
-    database
+#[derive(Subcommand)]
+enum TreeCommand {
+    /// Create a certificate tree rooted at the admin holding the key
+    Create {
+        certificate: String,
+        /// Secret key file of the admin
+        #[arg(long)]
+        key: PathBuf,
+    },
+
+    /// Replace the rules of a certificate tree with a JSON policy file
+    Policy {
+        certificate: String,
+        #[arg(long)]
+        file: PathBuf,
+        /// Secret key file of the admin
+        #[arg(long)]
+        key: PathBuf,
+    },
+
+    /// Show the actions the policy's rules took by themselves
+    Audit { certificate: String },
 }
 
-fn build_user_tree () -> HashMap<String, User> {
-
-    let users: HashMap<String, User> = HashMap::new();
-
-    users
+/// A user listed in an import file.
+#[derive(Deserialize)]
+struct ImportedUser {
+    user_id: String,
+    parent: String,
+    #[serde(default)]
+    role: Role,
+    #[serde(default)]
+    limits: Limits,
+    public_key: PublicKey,
 }
 
-/// Adds a new user tree to the database based on certificate
-fn add_user_tree (certificate: &String,
-                  database: &mut HashMap<String, HashMap<String, User>>,
-                  statistics: &mut Statistics) -> Result<(), String> {
-
-    if database.contains_key(certificate) {
-
-        return Err(format!("User tree under certificate '{certificate}' already exists in database."));
-    }
-
-    let users: HashMap<String, User> = build_user_tree ();
-
-    database.insert(certificate.to_string(), users);
-
-
-    // Assign an admin
-    let admin_id: String = String::from("admin");
-    let admin: User = User{ id: admin_id.clone(),
-        parent: admin_id.clone(),
-        children: Vec::new(),
-        reports: 0,
-        blocked: false};
-
+#[derive(Subcommand)]
+enum UserCommand {
+    /// Add a user as a child of parent
+    Add {
+        user_id: String,
+        #[arg(long)]
+        parent: String,
+        #[arg(long)]
+        cert: String,
+        /// Public key of the new user
+        #[arg(long)]
+        public_key: PublicKey,
+        /// Role granted to the new user, at most the parent's own
+        #[arg(long, default_value_t = Role::default())]
+        role: Role,
+        /// Number of children each user of the new user's subtree may have at most
+        #[arg(long)]
+        max_children: Option<u32>,
+        /// Unix time after which the new user's subtree loses its permission
+        #[arg(long)]
+        valid_until: Option<u64>,
+        /// Secret key file of the parent
+        #[arg(long)]
+        key: PathBuf,
+    },
+
+    /// Add the users listed in a JSON file, all of them or none
+    Import {
+        /// JSON array of `{"user_id", "parent", "public_key", "role"?, "limits"?}`
+        file: PathBuf,
+        #[arg(long)]
+        cert: String,
+        /// Secret key file of the parents, which may include users added earlier in the file
+        #[arg(long)]
+        key: PathBuf,
+    },
+
+    /// Block a user, as its moderating parent, an administering ancestor or the user itself
+    Block {
+        user_id: String,
+        #[arg(long)]
+        by: String,
+        #[arg(long)]
+        cert: String,
+        /// Seconds after which the block ends by itself, leaving the user under its parent
+        #[arg(long)]
+        duration: Option<u64>,
+        /// One of spam, abuse, impersonation, fraud, rule, appeal, resolved or other
+        #[arg(long, default_value = "other")]
+        reason: BlockReason,
+        /// Why, in words
+        #[arg(long)]
+        text: Option<String>,
+        /// Secret key file of the blocker
+        #[arg(long)]
+        key: PathBuf,
+    },
+
+    /// Unblock a user and move it under the unblocker
+    Unblock {
+        user_id: String,
+        #[arg(long)]
+        by: String,
+        #[arg(long)]
+        cert: String,
+        /// One of spam, abuse, impersonation, fraud, rule, appeal, resolved or other
+        #[arg(long, default_value = "other")]
+        reason: BlockReason,
+        /// Why, in words
+        #[arg(long)]
+        text: Option<String>,
+        /// Secret key file of the unblocker
+        #[arg(long)]
+        key: PathBuf,
+    },
+
+    /// Remove a user, moving its children under its parent unless told otherwise
+    Remove {
+        user_id: String,
+        #[arg(long)]
+        by: String,
+        #[arg(long)]
+        cert: String,
+        /// Remove the user's whole subtree with it
+        #[arg(long, conflicts_with = "adopter")]
+        cascade: bool,
+        /// Move the user's children under this user instead of its parent
+        #[arg(long)]
+        adopter: Option<String>,
+        /// Secret key file of the remover
+        #[arg(long)]
+        key: PathBuf,
+        /// User approving the removal for the children's new parent, repeated with --approver-key
+        #[arg(long)]
+        approver: Vec<String>,
+        /// Secret key file of the approver at the same position
+        #[arg(long)]
+        approver_key: Vec<PathBuf>,
+    },
+
+    /// Move a user and its subtree under a new parent
+    Move {
+        user_id: String,
+        #[arg(long)]
+        to: String,
+        #[arg(long)]
+        by: String,
+        #[arg(long)]
+        cert: String,
+        /// Secret key file of the mover
+        #[arg(long)]
+        key: PathBuf,
+        /// User approving the move, as the new parent or the tree's move policy asks, repeated with --approver-key
+        #[arg(long)]
+        approver: Vec<String>,
+        /// Secret key file of the approver at the same position
+        #[arg(long)]
+        approver_key: Vec<PathBuf>,
+    },
+
+    /// Report a user, counted once per reporter for the user and all its ancestors
+    Report {
+        user_id: String,
+        #[arg(long)]
+        by: String,
+        #[arg(long)]
+        cert: String,
+        /// One of spam, abuse, impersonation, fraud or other
+        #[arg(long, default_value = "other")]
+        reason: ReportReason,
+        /// What the user did
+        #[arg(long)]
+        text: Option<String>,
+        /// Secret key file of the reporter
+        #[arg(long)]
+        key: PathBuf,
+    },
+}
 
-    database.get_mut(certificate).unwrap().insert(admin_id, admin);
-    statistics.user_add += 1;
+#[derive(Subcommand)]
+enum InviteCommand {
+    /// Print an invitation token signed by the parent
+    Create {
+        #[arg(long)]
+        parent: String,
+        #[arg(long)]
+        cert: String,
+        /// The only user id the invitation can be redeemed for
+        #[arg(long)]
+        user: Option<String>,
+        /// Role granted to the redeeming user, at most the parent's own
+        #[arg(long, default_value_t = Role::default())]
+        role: Role,
+        /// Number of children each user of the redeeming user's subtree may have at most
+        #[arg(long)]
+        max_children: Option<u32>,
+        /// Unix time after which the redeeming user's subtree loses its permission
+        #[arg(long)]
+        valid_until: Option<u64>,
+        /// Seconds until the invitation expires
+        #[arg(long, default_value_t = 7 * 24 * 60 * 60)]
+        expires_in: u64,
+        /// Secret key file of the parent
+        #[arg(long)]
+        key: PathBuf,
+    },
+
+    /// Join a certificate tree with an invitation token
+    Redeem {
+        token: SignedInvitation,
+        user_id: String,
+        /// Secret key file the new user signs with
+        #[arg(long)]
+        key: PathBuf,
+    },
+}
 
-    println!("Added user tree under certificate '{certificate}'");
+#[derive(Subcommand)]
+enum AppealCommand {
+    /// Appeal the block of a user, as the blocked user itself
+    File {
+        user_id: String,
+        #[arg(long)]
+        cert: String,
+        /// Why the block should be lifted
+        #[arg(long)]
+        statement: String,
+        /// Secret key file of the blocked user
+        #[arg(long)]
+        key: PathBuf,
+    },
+
+    /// Vote on an appeal as one of its reviewers
+    Review {
+        appeal: u64,
+        #[arg(long)]
+        by: String,
+        #[arg(long)]
+        cert: String,
+        /// Vote to lift the block
+        #[arg(long, conflicts_with = "reject", required_unless_present = "reject")]
+        accept: bool,
+        /// Vote to keep the block
+        #[arg(long)]
+        reject: bool,
+        /// Leave the user under its parent instead of moving it under the reviewer if the appeal is accepted
+        #[arg(long, conflicts_with = "reject")]
+        keep_parent: bool,
+        /// Secret key file of the reviewer
+        #[arg(long)]
+        key: PathBuf,
+    },
+
+    /// List the appeals of a certificate tree
+    List {
+        #[arg(long)]
+        cert: String,
+        /// Only appeals in this state, one of pending, accepted, rejected or lapsed
+        #[arg(long)]
+        state: Option<AppealState>,
+    },
+
+    /// Show an appeal with its reviewers and votes
+    Show {
+        appeal: u64,
+        #[arg(long)]
+        cert: String,
+    },
+}
 
-    Ok(())
+#[derive(Subcommand)]
+enum ProofCommand {
+    /// Print the proof of a user's permission as JSON
+    Export {
+        user_id: String,
+        #[arg(long)]
+        cert: String,
+    },
+
+    /// Verify a proof file against the admin's public key, without the database
+    Verify {
+        proof: PathBuf,
+        #[arg(long)]
+        admin_key: PublicKey,
+        /// Blocked user to reject proofs through, can be repeated
+        #[arg(long)]
+        revoked: Vec<String>,
+        /// Unix time to check the expiry of delegations at, now by default
+        #[arg(long)]
+        at: Option<u64>,
+    },
 }
 
-/// Checks if a user has permission in a user tree.
-fn check_user_permission(user_id: &str,
-                         certificate: &String,
-                         database: &HashMap<String, HashMap<String, User>>,
-                         statistics: &mut Statistics)
-                         -> Result<bool, String> {
+#[derive(Subcommand)]
+enum MerkleCommand {
+    /// Print the root hash of a certificate tree
+    Root {
+        #[arg(long)]
+        cert: String,
+    },
+
+    /// Print the inclusion proof of a user's record as JSON
+    Prove {
+        user_id: String,
+        #[arg(long)]
+        cert: String,
+    },
+
+    /// Verify an inclusion proof file against a root hash, without the database
+    Verify {
+        proof: PathBuf,
+        #[arg(long)]
+        root: Digest,
+    },
+}
 
-    let mut current_id = user_id;
+fn log_path(database: &Path) -> PathBuf {
+    let mut path = database.as_os_str().to_owned();
+    path.push(".log");
+    PathBuf::from(path)
+}
 
-    match database.get(certificate) {
-        Some(users) => {
-            loop {
-                match users.get(current_id) {
+fn read_key(path: &Path) -> Result<SecretKey> {
+    let storage_error = |message: String| Error::Storage {
+        path: path.display().to_string(),
+        message,
+    };
 
-                    Some(user) => {
+    let text = fs::read_to_string(path).map_err(|error| storage_error(error.to_string()))?;
+    text.parse()
+        .map_err(|error: ParseKeyError| storage_error(error.to_string()))
+}
 
-                        statistics.user_read += 1;
+/// Writes a secret key to a new file only its owner may read, refusing to
+/// replace an existing file.
+fn write_key(path: &Path, key: &SecretKey) -> Result<()> {
+    let storage_error = |error: std::io::Error| Error::Storage {
+        path: path.display().to_string(),
+        message: error.to_string(),
+    };
 
-                        if user.blocked {
-                            return Ok(false);
-                        }
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
 
-                        // Only admin has identical user id and parent id
-                        if user.parent == current_id {
-                            break;
-                        }
+    #[cfg(unix)]
+    options.mode(0o600);
 
-                        current_id = &user.parent;
+    let mut file = options.open(path).map_err(storage_error)?;
+    file.write_all(format!("{key}\n").as_bytes()).map_err(storage_error)?;
+    file.sync_all().map_err(storage_error)
+}
 
-                    },
-                    None => {
-                        return Err(format!("user '{current_id}' not found in certificate tree '{certificate}'."));
-                    }
-                };
-            }
-        },
-        None => {
-            return Err(format!("certificate '{certificate}' not found in database."));
-        }
+/// Reads a JSON document such as an exported proof.
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T> {
+    let storage_error = |message: String| Error::Storage {
+        path: path.display().to_string(),
+        message,
     };
 
-    // println!("user {user_id} has permission in certificate '{certificate}'");
-
-    Ok(true)
+    let text = fs::read_to_string(path).map_err(|error| storage_error(error.to_string()))?;
+    serde_json::from_str(&text).map_err(|error| storage_error(error.to_string()))
 }
 
-/// Add a user to user tree in the database based on certificate
-fn add_user(user_id: &String,
-            parent: &String,
-            certificate: &String,
-            database: &mut HashMap<String, HashMap<String, User>>,
-            statistics: &mut Statistics)
-            -> Result<(), String> {
-
-    if *user_id == *parent {
-        return Err("parent id must not be identical to user id".to_string());
-    }
-
-    match check_user_permission(parent, certificate, database, statistics) {
-        Ok(x) => {
-            if !x {
-                return Err(format!("parent {parent} does not have permission."))
-            }
-        }
-        Err(error) => {
-            return Err(format!("Error for parent {parent}: ") + &error)
-        }
+/// Refuses the default database directory while the default database file of
+/// earlier versions is still around, so that it does not start out empty.
+fn check_default(path: &Path) -> Result<&Path> {
+    let legacy = Path::new(LEGACY_DATABASE);
+
+    if path == Path::new(DEFAULT_DATABASE) && !path.exists() && legacy.is_file() {
+        return Err(Error::Storage {
+            path: LEGACY_DATABASE.to_owned(),
+            message: format!(
+                "database file of an earlier version, convert it with `--database {LEGACY_DATABASE}` \
+                 or rename it and its log to `{DEFAULT_DATABASE}` and `{DEFAULT_DATABASE}.log`"
+            ),
+        });
     }
 
-    match database.get_mut(certificate) {
-        Some(users) => {
-
-            if users.contains_key(user_id) {
-                return Err(format!("user '{user_id}' already exists in database."));
-            }
-
-            // Update Parent
-            match users.get_mut(parent) {
-                Some(user) => {
-
-                    statistics.user_read += 1;
-                    statistics.user_update += 1;
-
-                    if user.children.contains(user_id) {
-                        return Err(format!("parent '{parent}' already added user '{user_id}' in database."));
-                    }
-
-                    user.children.push(user_id.to_string());
-                },
-                None => {
-                    return Err(format!("parent '{parent}' not found in certificate tree '{certificate}'."));
-                }
-            };
+    Ok(path)
+}
 
-            // Add User
-            let new_user: User = User::new(user_id, parent);
-            users.insert(user_id.clone(), new_user);
+/// Opens the database, rebuilding it from the event log if it is missing.
+fn open_database(path: &Path) -> Result<Database<FileStorage, FileEventLog>> {
+    let storage = FileStorage::open(check_default(path)?)?;
+    let log = FileEventLog::open(log_path(path))?;
 
-            statistics.user_add += 1;
+    Database::replay(storage, log)
+}
 
+fn run(cli: &Cli) -> Result<u8> {
+    let json = cli.json;
 
-        },
+    // A key belongs to no database, and a database that fails to load must
+    // not keep users from generating one
+    if let Command::Key(KeyCommand::Generate { out }) = &cli.command {
+        let key = SecretKey::generate();
+        write_key(out, &key)?;
 
-        None => {
-            return Err(format!("certificate '{certificate}' not found in database."));
+        if json {
+            println!("{}", json!({ "public_key": key.public_key() }));
+        } else {
+            println!("public key: {}", key.public_key());
         }
-    };
 
-    println!("user '{user_id}' added by parent '{parent}' under certificate '{certificate}' in database");
-
-    Ok(())
-}
+        return Ok(EXIT_OK);
+    }
 
-/// Increments the report count for a user and all its ancestors.
-fn report_user(user_id: &String, 
-               certificate: &String, 
-               database: &mut HashMap<String, HashMap<String, User>>,
-               statistics: &mut Statistics) 
-               -> Result<(), String> {
+    // Verifying a proof needs nothing but the proof and the admin's key
+    if let Command::Proof(ProofCommand::Verify {
+        proof,
+        admin_key,
+        revoked,
+        at,
+    }) = &cli.command
+    {
+        let proof: PermissionProof = read_json(proof)?;
+        let now = at.unwrap_or_else(unix_time);
+
+        let public_key = proof.verify(admin_key, &revoked.iter().cloned().collect(), now)?;
+
+        if json {
+            println!(
+                "{}",
+                json!({ "certificate": proof.certificate, "user_id": proof.user_id, "public_key": public_key })
+            );
+        } else {
+            println!(
+                "user {} has permission in certificate '{}' with key {public_key}.",
+                proof.user_id, proof.certificate
+            );
+        }
 
-    let mut current_id = user_id.clone();
+        return Ok(EXIT_OK);
+    }
 
-    match database.get_mut(certificate) {
-        Some(users) => {
-            loop {
-                match users.get_mut(&current_id) {
+    if let Command::Merkle(MerkleCommand::Verify { proof, root }) = &cli.command {
+        let proof: InclusionProof = read_json(proof)?;
+        let verified = proof.verify(root);
+
+        if json {
+            println!(
+                "{}",
+                json!({ "certificate": proof.certificate, "user_id": proof.user.id, "verified": verified })
+            );
+        } else if verified {
+            println!(
+                "user {} is committed to by root {root} of certificate '{}'.",
+                proof.user.id, proof.certificate
+            );
+        } else {
+            println!(
+                "user {} is not committed to by root {root} of certificate '{}'.",
+                proof.user.id, proof.certificate
+            );
+        }
 
-                    Some(user) => {
+        return Ok(if verified { EXIT_OK } else { EXIT_DENIED });
+    }
 
-                        statistics.user_read += 1;
-                        statistics.user_update += 1;
+    // Checking needs the trees as stored, which the database may refuse to load
+    if let Command::Fsck { repair } = &cli.command {
+        let reports = FileStorage::fsck(check_default(&cli.database)?, *repair)?;
+        let mut remaining = 0;
 
-                        user.reports += 1;
+        for report in reports.iter() {
+            remaining += report
+                .violations
+                .iter()
+                .filter(|violation| !report.repaired || !violation.is_repairable())
+                .count();
 
-                        // Only admin has identical user id and parent id
-                        if user.parent == current_id {
-                            break;
-                        }
+            if json {
+                continue;
+            }
 
-                        current_id = user.parent.clone();                        
+            if report.violations.is_empty() {
+                println!("certificate '{}': ok", report.certificate);
+                continue;
+            }
 
-                    },
-                    None => {
-                        return Err(format!("user '{current_id}' not found in certificate tree '{certificate}'."));
-                    }
+            println!(
+                "certificate '{}': {} violation(s)",
+                report.certificate,
+                report.violations.len()
+            );
+
+            for violation in report.violations.iter() {
+                let status = if report.repaired && violation.is_repairable() {
+                    "repaired"
+                } else {
+                    "found"
                 };
+                println!("  {status}: {violation}");
             }
-        },
-        None => {
-            return Err(format!("certificate '{certificate}' not found in database."));
         }
-    };
-
-    Ok(())    
-}
-
-/// Blocks a user from having permission in a certificate tree.
-fn block_user(user_id: &String,
-              blocker: &String,
-              certificate: &String,
-              database: &mut HashMap<String, HashMap<String, User>>,
-              statistics: &mut Statistics)
-              -> Result<(), String> {
-
-    match database.get_mut(certificate) {
-        Some(users) => {
-            match users.get_mut(user_id) {
-                Some(user) => {
 
-                    statistics.user_read += 1;
-
-                    if user.blocked {
-                        return Err(format!("user {user_id} has already been blocked."));
-                    }
+        if json {
+            println!("{}", json!(reports));
+        }
 
-                    if user.parent != *blocker && *user_id != *blocker {
-                        return Err("Only user's parent or themselves can block the user.".to_string());
-                    }
+        return Ok(if remaining == 0 { EXIT_OK } else { EXIT_CORRUPT });
+    }
 
-                    user.blocked = true;
-                    statistics.user_update += 1;
+    let database = open_database(&cli.database)?;
 
-                },
-
-                None => {
-                    return Err(format!("user '{user_id}' not found in certificate tree '{certificate}'."));
-                }
-            };
-        },
-        None => {
-            return Err(format!("certificate '{certificate}' not found in database."));
+    let done = |message: String, value: serde_json::Value| {
+        if json {
+            println!("{value}");
+        } else {
+            println!("{message}");
         }
     };
 
-    println!("user {user_id} blocked under certificate '{certificate}'");
-
-    Ok(())
-}
+    match &cli.command {
+        Command::Tree(TreeCommand::Create { certificate, key }) => {
+            let key = read_key(key)?;
+            let event = Event::AddUserTree {
+                certificate: certificate.clone(),
+                admin_key: key.public_key(),
+            };
 
-fn unblock_user(user_id: &String,
-                unblocker: &String,
-                certificate: &String,
-                database: &mut HashMap<String, HashMap<String, User>>,
-                statistics: &mut Statistics)
-                -> Result<(), String> {
-
-    // Check if unblocker has permission
-    match check_user_permission(unblocker, certificate, database, statistics) {
-        Ok(x) => {
-            if !x {
-                return Err(format!("unblocker {unblocker} does not have permission."))
-            }
-        }
-        Err(error) => {
-            return Err(format!("Error for unblocker {unblocker}: ") + &error)
+            database.add_user_tree(certificate, key.public_key(), database.authorize(&event, &key))?;
+            done(
+                format!("Added user tree under certificate '{certificate}'"),
+                json!({ "certificate": certificate }),
+            );
         }
-    }
+        Command::Tree(TreeCommand::Policy { certificate, file, key }) => {
+            let key = read_key(key)?;
+            let policy: Policy = read_json(file)?;
+            let event = Event::SetPolicy {
+                certificate: certificate.clone(),
+                policy: policy.clone(),
+            };
 
-    // Check if user has already blocked
-    match check_user_permission(user_id, certificate, database, statistics) {
-        Ok(x) => {
-            if x {
-                return Err(format!("user {user_id} already has permission."))
-            }
+            database.set_policy(policy.clone(), certificate, database.authorize(&event, &key))?;
+            done(
+                format!("policy of certificate '{certificate}' replaced"),
+                json!({ "certificate": certificate, "policy": policy }),
+            );
         }
-        Err(error) => {
-            return Err(format!("Error for user {user_id}: ") + &error)
-        }
-    }
-
-    // Move user from a parent to another
-    match database.get_mut(certificate) {
-        Some(users) => {
+        Command::Tree(TreeCommand::Audit { certificate }) => {
+            let audit = database.audit_trail(certificate)?;
 
-            let previous_parent;
-
-            // Unblock user and update its parent
-            match users.get_mut(user_id) {
-
-                Some(user) => {
-
-                    statistics.user_read += 1;
-                    statistics.user_update += 1;
-
-                    previous_parent = user.parent.clone();
-
-                    user.blocked = false;
-                    user.parent = unblocker.to_string();
-                },
-
-                None => {
-                    return Err(format!("user '{user_id}' not found in certificate tree '{certificate}'."));
+            if json {
+                println!("{}", json!({ "certificate": certificate, "audit": audit }));
+            } else {
+                for entry in audit.iter() {
+                    println!("{}: {} by rule '{}'", entry.time, entry.action, entry.rule);
                 }
+            }
+        }
+        Command::User(UserCommand::Add {
+            user_id,
+            parent,
+            cert,
+            public_key,
+            role,
+            max_children,
+            valid_until,
+            key,
+        }) => {
+            let key = read_key(key)?;
+            let limits = Limits {
+                max_children: *max_children,
+                valid_until: *valid_until,
+            };
+            let event = Event::AddUser {
+                certificate: cert.clone(),
+                user_id: user_id.clone(),
+                parent: parent.clone(),
+                role: *role,
+                limits,
+                public_key: *public_key,
             };
 
-            // Remove user from previous parent's children
-            match users.get_mut(&previous_parent) {
-                Some(user) => {
+            database.add_user(
+                user_id,
+                parent,
+                *role,
+                limits,
+                *public_key,
+                cert,
+                database.authorize(&event, &key),
+            )?;
+            done(
+                format!("user '{user_id}' added by parent '{parent}' as {role} under certificate '{cert}' in database"),
+                json!({ "certificate": cert, "user_id": user_id, "parent": parent, "role": role }),
+            );
+        }
+        Command::User(UserCommand::Import { file, cert, key }) => {
+            let key = read_key(key)?;
+            let users: Vec<ImportedUser> = read_json(file)?;
+            let mut transaction = database.transaction(cert);
+
+            for user in users.iter() {
+                let event = Event::AddUser {
+                    certificate: cert.clone(),
+                    user_id: user.user_id.clone(),
+                    parent: user.parent.clone(),
+                    role: user.role,
+                    limits: user.limits,
+                    public_key: user.public_key,
+                };
 
-                    statistics.user_read += 1;
-                    statistics.user_update += 1;
+                let authorization = transaction.authorize(&event, &key);
+                transaction.push(event, Some(authorization))?;
+            }
 
-                    let index = user.children.iter().position(|x| *x == user_id.clone()).unwrap();
-                    user.children.remove(index);
+            transaction.commit()?;
 
-                },
-                None => {
-                    return Err(format!("user '{user_id}' not found in certificate tree '{certificate}'."));
-                }
+            let user_ids: Vec<&str> = users.iter().map(|user| user.user_id.as_str()).collect();
+            done(
+                format!("{} users added under certificate '{cert}' in database", users.len()),
+                json!({ "certificate": cert, "user_ids": user_ids }),
+            );
+        }
+        Command::User(UserCommand::Block {
+            user_id,
+            by,
+            cert,
+            duration,
+            reason,
+            text,
+            key,
+        }) => {
+            let key = read_key(key)?;
+            let event = Event::BlockUser {
+                certificate: cert.clone(),
+                user_id: user_id.clone(),
+                blocker: by.clone(),
+                duration: *duration,
+                reason: *reason,
+                text: text.clone(),
             };
 
-            // Update unblocker's children
-            match users.get_mut(unblocker) {
-                Some(user) => {
-
-                    statistics.user_read += 1;
-                    statistics.user_update += 1;
-
-                    user.children.push(user_id.to_string());
+            database.block_user(
+                user_id,
+                by,
+                *duration,
+                *reason,
+                text.as_deref(),
+                cert,
+                database.authorize(&event, &key),
+            )?;
+            let message = match duration {
+                Some(duration) => format!("user {user_id} blocked for {duration} seconds under certificate '{cert}'"),
+                None => format!("user {user_id} blocked under certificate '{cert}'"),
+            };
 
+            done(
+                message,
+                json!({ "certificate": cert, "user_id": user_id, "blocker": by, "duration": duration, "reason": reason }),
+            );
+        }
+        Command::User(UserCommand::Unblock {
+            user_id,
+            by,
+            cert,
+            reason,
+            text,
+            key,
+        }) => {
+            let key = read_key(key)?;
+            let event = database.unblock_event(user_id, by, *reason, text.as_deref(), cert);
+
+            database.unblock_user(
+                user_id,
+                by,
+                *reason,
+                text.as_deref(),
+                cert,
+                database.authorize(&event, &key),
+            )?;
+            done(
+                format!("user {user_id} unblocked under certificate '{cert}'"),
+                json!({ "certificate": cert, "user_id": user_id, "unblocker": by, "reason": reason }),
+            );
+        }
+        Command::User(UserCommand::Remove {
+            user_id,
+            by,
+            cert,
+            cascade,
+            adopter,
+            key,
+            approver,
+            approver_key,
+        }) => {
+            let key = read_key(key)?;
+            let orphans = match (cascade, adopter) {
+                (true, _) => Orphans::Cascade,
+                (false, Some(adopter)) => Orphans::Adopt {
+                    adopter: adopter.clone(),
                 },
-                None => {
-                    return Err(format!("user '{user_id}' not found in certificate tree '{certificate}'."));
-                }
+                (false, None) => Orphans::ReattachToParent,
             };
+            let event = database.remove_event(user_id, by, orphans.clone(), cert);
+            let mut approvals = Vec::new();
 
+            for (approver, approver_key) in approver.iter().zip(approver_key) {
+                approvals.push(database.approve(&event, approver, &read_key(approver_key)?));
+            }
 
-        },
-        None => {
-            return Err(format!("certificate '{certificate}' not found in database."));
+            database.remove_user(
+                user_id,
+                by,
+                orphans.clone(),
+                cert,
+                database.authorize(&event, &key),
+                approvals,
+            )?;
+            done(
+                format!("user {user_id} removed under certificate '{cert}', children: {orphans}"),
+                json!({ "certificate": cert, "user_id": user_id, "remover": by, "orphans": orphans }),
+            );
         }
-    };
-
-    println!("user {user_id} unblocked under certificate '{certificate}'");
-    Ok(())
-}
-
-/// Creates a hierarchical user tree for testing purposes.
-fn make_user_tree_test(branch: u16,
-                       level: u16,
-                       certificate: &String,
-                       database: &mut HashMap<String, HashMap<String, User>>,
-                       statistics: &mut Statistics) {
-
-    let mut parents: Vec<String> = Vec::new();
-    parents.push(String::from("admin"));
-
-    for _l in 1..level {
-
-        let mut next_parents: Vec<String> = Vec::new();
-
-        for parent in parents.iter() {
-            for b in 1..branch {
-
-                let id = format!("{parent}-{b}");
-                let _ = add_user(&id, parent, certificate, database, statistics);
-
-                next_parents.push(id)
+        Command::User(UserCommand::Move {
+            user_id,
+            to,
+            by,
+            cert,
+            key,
+            approver,
+            approver_key,
+        }) => {
+            let key = read_key(key)?;
+            let event = database.move_event(user_id, to, by, cert);
+            let mut approvals = Vec::new();
+
+            for (approver, approver_key) in approver.iter().zip(approver_key) {
+                approvals.push(database.approve(&event, approver, &read_key(approver_key)?));
             }
 
+            database.move_user(user_id, to, by, cert, database.authorize(&event, &key), approvals)?;
+            done(
+                format!("user {user_id} moved under {to} in certificate '{cert}'"),
+                json!({ "certificate": cert, "user_id": user_id, "parent": to, "mover": by }),
+            );
         }
-
-        parents = next_parents.clone();
-        next_parents.clear();
-
-    }
-
-}
-
-/// Prints information about a specific user.
-fn print_user_info(id: &String, certificate: &String, database: &HashMap<String, HashMap<String, User>>) {
-
-    match database.get(certificate) {
-        Some(users) => {
-            match users.get(id) {
-                Some(user) => {
-
-                    println!("############ User Info ############");
-                    println!("user id: {}", id);
-                    println!("user certificate: {}", certificate);
-                    println!("user parent: {}", user.parent);
-                    println!("user children: {:?}", user.children);
-                    println!("user reports: {}", user.reports);
-                    println!("user blocked: {}", user.blocked);
-                },
-                None => {
-                    println!("user '{id}' not found in certificate tree '{certificate}'.");
-                }
+        Command::Invite(InviteCommand::Create {
+            parent,
+            cert,
+            user,
+            role,
+            max_children,
+            valid_until,
+            expires_in,
+            key,
+        }) => {
+            let key = read_key(key)?;
+            let expires_at = unix_time() + expires_in;
+            let limits = Limits {
+                max_children: *max_children,
+                valid_until: *valid_until,
             };
-        },
-        None => {
-            println!("certificate '{certificate}' not found in database.");
-        }
-    };
-
-}
+            let invitation = Invitation::new(cert, parent, user.as_deref(), *role, limits, expires_at).sign(&key);
 
-fn print_statistics(statistics: &Statistics){
-
-    println!("***************Statistics***************");
-    println!("Number of adding user: {}", statistics.user_add);
-    println!("Number of reading user: {}", statistics.user_read);
-    println!("Number of updating user: {}", statistics.user_update);
-
-}
-
-fn print_user_tree_info(certificate: &String, database: &HashMap<String, HashMap<String, User>>)  {
+            done(
+                invitation.to_string(),
+                json!({ "token": invitation.to_string(), "expires_at": expires_at }),
+            );
+        }
+        Command::Invite(InviteCommand::Redeem { token, user_id, key }) => {
+            let key = read_key(key)?;
+            let parent = token.invitation.parent.clone();
+            let cert = token.invitation.certificate.clone();
+            let event = Event::RedeemInvitation {
+                user_id: user_id.clone(),
+                public_key: key.public_key(),
+                invitation: token.clone(),
+            };
 
-    println!("***************User Tree Information***************");
-    match database.get(certificate) {
-        Some(users) => {
+            database.redeem_invitation(
+                token.clone(),
+                user_id,
+                key.public_key(),
+                database.authorize(&event, &key),
+            )?;
+            done(
+                format!("user '{user_id}' added by parent '{parent}' under certificate '{cert}' in database"),
+                json!({ "certificate": cert, "user_id": user_id, "parent": parent }),
+            );
+        }
+        Command::User(UserCommand::Report {
+            user_id,
+            by,
+            cert,
+            reason,
+            text,
+            key,
+        }) => {
+            let key = read_key(key)?;
+            let event = Event::ReportUser {
+                certificate: cert.clone(),
+                user_id: user_id.clone(),
+                reporter: by.clone(),
+                reason: *reason,
+                text: text.clone(),
+            };
 
-            println!("Number of users under certificate '{}': '{}'", certificate, users.keys().len());
-            
-            let mut average: f32 = 0.0;
+            database.report_user(
+                user_id,
+                by,
+                *reason,
+                text.as_deref(),
+                cert,
+                database.authorize(&event, &key),
+            )?;
+            done(
+                format!("user {user_id} reported by {by} for {reason} under certificate '{cert}'"),
+                json!({ "certificate": cert, "user_id": user_id, "reporter": by, "reason": reason }),
+            );
+        }
+        Command::Appeal(AppealCommand::File {
+            user_id,
+            cert,
+            statement,
+            key,
+        }) => {
+            let key = read_key(key)?;
+            let event = Event::FileAppeal {
+                certificate: cert.clone(),
+                user_id: user_id.clone(),
+                statement: statement.clone(),
+            };
 
-            let mut average_excluded_childless: f32 = 0.0;
-            let mut n: u32 = 0;
+            let appeal = database.file_appeal(user_id, statement, cert, database.authorize(&event, &key))?;
 
-            for (_key, user) in users {
-                average += user.children.len() as f32;
+            done(
+                format!(
+                    "appeal {} of user {user_id} filed under certificate '{cert}', reviewers: {}",
+                    appeal.id,
+                    appeal.reviewers.join(", ")
+                ),
+                json!({ "certificate": cert, "appeal": appeal }),
+            );
+        }
+        Command::Appeal(AppealCommand::Review {
+            appeal,
+            by,
+            cert,
+            accept,
+            keep_parent,
+            key,
+            ..
+        }) => {
+            let key = read_key(key)?;
+            let event = database.review_event(*appeal, by, *accept, *keep_parent, cert);
+
+            let appeal = database.review_appeal(
+                *appeal,
+                by,
+                *accept,
+                *keep_parent,
+                cert,
+                database.authorize(&event, &key),
+            )?;
+
+            done(
+                format!(
+                    "appeal {} of user {} reviewed by {by}, {}",
+                    appeal.id, appeal.user_id, appeal.state
+                ),
+                json!({ "certificate": cert, "appeal": appeal }),
+            );
+        }
+        Command::Appeal(AppealCommand::List { cert, state }) => {
+            let mut appeals = database.appeals(cert)?;
+            appeals.retain(|appeal| state.is_none_or(|state| appeal.state == state));
 
-                if !(user.children.len() == 0) {
-                    n += 1;
-                    average_excluded_childless += user.children.len() as f32;
+            if json {
+                println!("{}", json!({ "certificate": cert, "appeals": appeals }));
+            } else {
+                for appeal in appeals.iter() {
+                    println!("{appeal}");
                 }
             }
-
-            average /= users.keys().len() as f32;
-            average_excluded_childless /= n as f32;
-
-            eprintln!("Average of users' children under certificate '{}': '{}' (included childless)", certificate, average);
-            eprintln!("Average of users' children under certificate '{}': '{}' (excluded childless)", certificate, average_excluded_childless);
-            
-        },
-        None => {
-            println!("certificate '{certificate}' not found in database.");
         }
-    };
-}
-
-/// For test
-fn main() {
-
-    let mut database = build_database();
-    let mut statistics: Statistics = Default::default(); 
- 
-    if let Err(error) = add_user_tree(&String::from("post"), &mut database, &mut statistics) {
-        println!("{}", error)
-    }
-
-    if let Err(error) = add_user_tree(&String::from("comment"), &mut database, &mut statistics) {
-        println!("{}", error)
-    }
-
-    if let Err(error) = add_user_tree(&String::from("view"), &mut database, &mut statistics) {
-        println!("{}", error)
-    }
-
-    // Unsuccessful (user tree already exists)
-    if let Err(error) = add_user_tree(&String::from("view"), &mut database, &mut statistics) {
-        println!("{}", error)
-    }
-
-    // Unsuccessful (certificate does not exists)
-    if let Err(error) = check_user_permission(&String::from("hassan"), &String::from("jack"), &mut database, &mut statistics) {
-        println!("{}", error)
-    }
-
-    // Unsuccessful (identical parent and user ids)
-    if let Err(error) = add_user(&String::from("hassan"), &String::from("hassan"), &String::from("post"),  &mut database, &mut statistics) {
-        println!("{}", error)
-    }
-
-
-    if let Err(error) = add_user(&String::from("hassan-1"), &String::from("hassan"), &String::from("post"),  &mut database, &mut statistics) {
-        println!("{}", error)
-    }
-
-    // Unsuccessful (user 'admin-1' already exists)
-    if let Err(error) = add_user(&String::from("hassan-1"), &String::from("hassan"), &String::from("post"),  &mut database, &mut statistics) {
-        println!("{}", error)
-    }
+        Command::Appeal(AppealCommand::Show { appeal, cert }) => {
+            let appeal = database.appeal(*appeal, cert)?;
 
+            if json {
+                println!("{}", json!({ "certificate": cert, "appeal": appeal }));
+            } else {
+                println!("{appeal}");
 
-    // Unsuccessful (user 'admin-1' already exists)
-    if let Err(error) = add_user(&String::from("hassan-3-1"), &String::from("hassan-3"), &String::from("post"),  &mut database, &mut statistics) {
-        println!("{}", error)
-    }
-
-
-    let certificate: String = String::from("post");
-
-    make_user_tree_test(6,6, &certificate, &mut database, &mut statistics);
-
-    print_user_info(&String::from("admin"), &certificate, &database);
-    print_user_info(&String::from("admin-2"), &certificate, &database);
-    print_user_info(&String::from("admin-2-1"), &certificate, &database);
-
-    if let Err(error) = report_user(&String::from("admin-2-1"), &certificate, &mut database, &mut statistics) {
-        println!("{}", error)
-    }
-
-    if let Err(error) = report_user(&String::from("admin-2-1"), &certificate, &mut database, &mut statistics) {
-        println!("{}", error)
-    }
+                if let Some(blocker) = &appeal.blocker {
+                    println!("blocked by: {blocker}");
+                }
 
-    if let Err(error) = report_user(&String::from("admin-2-1-3"), &certificate, &mut database, &mut statistics) {
-        println!("{}", error)
-    }
+                println!("reviewers: {}", appeal.reviewers.join(", "));
 
-    print_user_info(&String::from("admin-2-1"), &certificate, &database);
-    print_user_info(&String::from("admin-2-1-3"), &certificate, &database); 
+                if let Some(designated) = &appeal.designated {
+                    println!("designated reviewer: {designated}");
+                }
 
+                println!("quorum: {}", appeal.quorum);
 
-    match check_user_permission(&String::from("admin-2-1-3"), &certificate, &database, &mut statistics) {
-        Ok(x) => {
-            if x {
-                println!("user admin-2-1-3 has permission.")
-            } else {
-                println!("user admin-2-1-3 does not have permission.")
+                for vote in appeal.votes.iter() {
+                    let decision = if vote.accept { "accepted" } else { "rejected" };
+                    println!("  {decision} by {} at {}", vote.reviewer, vote.time);
+                }
             }
         }
-
-        Err(error) => {
-            return println!("{}", error)
+        Command::Proof(ProofCommand::Export { user_id, cert }) => {
+            let proof = database.permission_proof(user_id, cert)?;
+            println!("{}", json!(proof));
         }
-    }
-
-    if let Err(error) = block_user(&String::from("admin-2-1-3"), &String::from("admin-2-1-3"), &certificate, &mut database, &mut statistics){
-        println!("{}", error)
-    }
-
-    match check_user_permission(&String::from("admin-2-1-3"), &certificate, &database, &mut statistics) {
-        Ok(x) => {
-            if x {
-                println!("user admin-2-1-3 has permission.")
-            } else {
-                println!("user admin-2-1-3 does not have permission.")
-            }
+        Command::Key(KeyCommand::Generate { .. }) => unreachable!("generated without the database above"),
+        Command::Proof(ProofCommand::Verify { .. }) => unreachable!("verified without the database above"),
+        Command::Merkle(MerkleCommand::Root { cert }) => {
+            let root = database.tree_root(cert)?;
+            done(root.to_string(), json!({ "certificate": cert, "root": root }));
         }
-
-        Err(error) => {
-            return println!("{}", error)
+        Command::Merkle(MerkleCommand::Prove { user_id, cert }) => {
+            let proof = database.inclusion_proof(user_id, cert)?;
+            println!("{}", json!(proof));
         }
-    }
-
-    // the same user and blocker
-    if let Err(error) = block_user(&String::from("admin-2-1-3"), &String::from("admin-2-1-3"), &certificate, &mut database, &mut statistics){
-        println!("{}", error)
-    }    
-
-    // Unsuccessful (blocker is not user's parent)
-    if let Err(error) = block_user(&String::from("admin-3-1-3"), &String::from("admin-2-1-3"), &certificate, &mut database, &mut statistics){
-        println!("{}", error)
-    }    
-
+        Command::Merkle(MerkleCommand::Verify { .. }) => unreachable!("verified without the database above"),
+        Command::Fsck { .. } => unreachable!("checked without the database above"),
+        Command::Check { user_id, cert } => {
+            let permission = database.check_user_permission(user_id, cert)?;
+            let message = if permission.is_granted() {
+                format!(
+                    "user {user_id} has permission with capabilities {} and limits: {}.",
+                    permission.capabilities, permission.limits
+                )
+            } else {
+                format!("user {user_id} does not have permission.")
+            };
 
-    //////////// Blocking a user by parent, unblocking again////////////////
-    if let Err(error) = block_user(&String::from("admin-3-1-3"), &String::from("admin-3-1"), &certificate, &mut database, &mut statistics){
-        println!("{}", error)
-    }    
+            done(
+                message,
+                json!({
+                    "certificate": cert,
+                    "user_id": user_id,
+                    "permission": permission.is_granted(),
+                    "capabilities": permission.capabilities,
+                    "limits": permission.limits,
+                }),
+            );
+
+            if !permission.is_granted() {
+                return Ok(EXIT_DENIED);
+            }
+        }
+        Command::Info { user_id, cert } => {
+            let info = database.user_info(user_id, cert)?;
 
-    match check_user_permission(&String::from("admin-3-1-3-1-1"), &certificate, &database, &mut statistics) {
-        Ok(x) => {
-            if x {
-                println!("user admin-3-1-3-1-1 has permission.")
+            if json {
+                println!("{}", json!(info));
             } else {
-                println!("user admin-3-1-3-1-1 does not have permission.")
+                print_user_info(&info);
             }
         }
+        Command::Stats { cert } => {
+            let certificates = match cert {
+                Some(cert) => vec![cert.clone()],
+                None => {
+                    let mut certificates = database.certificates();
+                    certificates.sort();
+                    certificates
+                }
+            };
 
-        Err(error) => {
-            return println!("{}", error)
-        }
-    }    
+            let mut infos = Vec::new();
+            let mut statistics = Statistics::default();
 
-    if let Err(error) = unblock_user(&String::from("admin-3-1-3"), &String::from("admin-3-1"), &certificate, &mut database, &mut statistics){
-        println!("{}", error)
-    }        
+            for certificate in certificates.iter() {
+                let info = database.tree_info(certificate)?;
+                statistics += info.statistics;
+                infos.push(info);
+            }
 
-    match check_user_permission(&String::from("admin-3-1-3-1-1"), &certificate, &database, &mut statistics) {
-        Ok(x) => {
-            if x {
-                println!("user admin-3-1-3-1-1 has permission.")
+            if json {
+                println!("{}", json!({ "trees": infos, "statistics": statistics }));
             } else {
-                println!("user admin-3-1-3-1-1 does not have permission.")
+                for info in infos.iter() {
+                    print_user_tree_info(info);
+                }
+                print_statistics(&statistics);
             }
         }
-
-        Err(error) => {
-            return println!("{}", error)
+        Command::Serve { bind } => {
+            let listener = TcpListener::bind(bind).map_err(|error| Error::Storage {
+                path: bind.clone(),
+                message: error.to_string(),
+            })?;
+
+            eprintln!("serving '{}' on http://{bind}", cli.database.display());
+
+            Server::new(database).serve(listener).map_err(|error| Error::Storage {
+                path: bind.clone(),
+                message: error.to_string(),
+            })?;
         }
-    }       
+    }
 
+    Ok(EXIT_OK)
+}
 
+fn exit_code(error: &Error) -> u8 {
+    match error.kind() {
+        ErrorKind::NotFound => EXIT_NOT_FOUND,
+        ErrorKind::Conflict => EXIT_CONFLICT,
+        ErrorKind::Unauthorized => EXIT_UNAUTHORIZED,
+        ErrorKind::Storage => EXIT_STORAGE,
+    }
+}
 
-    //////////// Blocking a user by parent, unblocking by another user////////////////
-    if let Err(error) = block_user(&String::from("admin-4-1-3"), &String::from("admin-4-1"), &certificate, &mut database, &mut statistics){
-        println!("{}", error)
-    }    
+fn main() -> ExitCode {
+    let cli = Cli::parse();
 
-    match check_user_permission(&String::from("admin-4-1-3"), &certificate, &database, &mut statistics) {
-        Ok(x) => {
-            if x {
-                println!("user admin-4-1-3 has permission.")
-            } else {
-                println!("user admin-4-1-3 does not have permission.")
-            }
+    // Approvals pair each approver with the key file at the same position
+    if let Command::User(
+        UserCommand::Move {
+            approver, approver_key, ..
         }
-
-        Err(error) => {
-            return println!("{}", error)
+        | UserCommand::Remove {
+            approver, approver_key, ..
+        },
+    ) = &cli.command
+    {
+        if approver.len() != approver_key.len() {
+            Cli::command()
+                .error(
+                    clap::error::ErrorKind::WrongNumberOfValues,
+                    "every --approver needs an --approver-key at the same position",
+                )
+                .exit();
         }
-    }    
-
-    if let Err(error) = unblock_user(&String::from("admin-4-1-3"), &String::from("admin-5-1"), &certificate, &mut database, &mut statistics){
-        println!("{}", error)
-    }            
-
-    print_user_info(&String::from("admin-4-1-3"), &certificate, &database);
+    }
 
+    match run(&cli) {
+        Ok(code) => ExitCode::from(code),
+        Err(error) => {
+            let code = exit_code(&error);
 
-    print_statistics(&statistics);
-    print_user_tree_info(&certificate, &database)
+            if cli.json {
+                println!("{}", json!({ "error": error.full_message(), "code": code }));
+            } else {
+                eprintln!("{}", error.full_message());
+            }
 
+            ExitCode::from(code)
+        }
+    }
 }
@@ -0,0 +1,359 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest as _, Sha256};
+
+use crate::delegation::Limits;
+use crate::identity::{decode_hex, encode_hex, hex_encoding, ParseKeyError, PublicKey};
+use crate::role::Role;
+use crate::user::User;
+
+/// Domain separation of leaf and inner node hashes.
+const LEAF_PREFIX: u8 = 0;
+const NODE_PREFIX: u8 = 1;
+
+/// SHA-256 digest, written as hex.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Digest([u8; 32]);
+
+impl Digest {
+    pub fn from_bytes(bytes: [u8; 32]) -> Digest {
+        Digest(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    fn node(left: &Digest, right: &Digest) -> Digest {
+        let mut hasher = Sha256::new();
+        hasher.update([NODE_PREFIX]);
+        hasher.update(left.0);
+        hasher.update(right.0);
+        Digest(hasher.finalize().into())
+    }
+}
+
+hex_encoding!(Digest, Digest::from_bytes, |digest: &Digest| digest.0);
+
+/// The fields of a user a tree commitment covers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommittedUser {
+    pub id: String,
+    pub parent: String,
+    pub children: Vec<String>,
+    pub reports: u16,
+    pub blocked: bool,
+    /// Unix time a temporary block ends at, none for a block for good.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blocked_until: Option<u64>,
+    pub role: Role,
+    #[serde(default, skip_serializing_if = "Limits::is_none")]
+    pub limits: Limits,
+    pub public_key: PublicKey,
+}
+
+impl CommittedUser {
+    pub fn new(user: &User) -> CommittedUser {
+        CommittedUser {
+            id: user.id.clone(),
+            parent: user.parent.clone(),
+            children: user.children.clone(),
+            reports: user.reports,
+            blocked: user.blocked,
+            blocked_until: user.blocked_until,
+            role: user.role,
+            limits: user.limits,
+            public_key: user.public_key,
+        }
+    }
+
+    pub fn leaf(&self) -> Digest {
+        let mut hasher = Sha256::new();
+        hasher.update([LEAF_PREFIX]);
+        hasher.update(serde_json::to_vec(self).expect("users serialize to JSON"));
+        Digest(hasher.finalize().into())
+    }
+}
+
+/// Binary Merkle tree over user leaves in the order users were added.
+///
+/// Every level is kept, so replacing or appending a leaf only rehashes the
+/// path from the leaf to the root. A node without a right sibling is carried
+/// up unchanged.
+#[derive(Debug, Clone, Default)]
+pub struct MerkleTree {
+    levels: Vec<Vec<Digest>>,
+}
+
+/// A sibling hash on the path from a leaf to the root.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofStep {
+    pub sibling: Digest,
+    /// Whether the sibling is the left child of the parent node.
+    pub left: bool,
+}
+
+impl MerkleTree {
+    pub fn root(&self) -> Digest {
+        self.levels
+            .last()
+            .and_then(|level| level.first())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Appends a leaf, returning its index.
+    pub fn push(&mut self, leaf: Digest) -> usize {
+        if self.levels.is_empty() {
+            self.levels.push(Vec::new());
+        }
+
+        let index = self.levels[0].len();
+        self.levels[0].push(leaf);
+        self.rehash(index);
+
+        index
+    }
+
+    /// Replaces the leaf at index.
+    pub fn update(&mut self, index: usize, leaf: Digest) {
+        self.levels[0][index] = leaf;
+        self.rehash(index);
+    }
+
+    /// Removes the leaf at index, moving the last leaf into its place.
+    pub fn swap_remove(&mut self, index: usize) {
+        let last = self.levels[0].len() - 1;
+
+        if index != last {
+            let leaf = self.levels[0][last];
+            self.update(index, leaf);
+        }
+
+        self.levels[0].pop();
+
+        // Drop the nodes that only covered the last leaf
+        for level in 1..self.levels.len() {
+            let len = self.levels[level - 1].len().div_ceil(2);
+            self.levels[level].truncate(len);
+        }
+
+        match self.levels[0].len() {
+            0 => self.levels.clear(),
+            len => self.rehash(len - 1),
+        }
+    }
+
+    /// Puts back a leaf a swap removal took from index, moving the leaf in
+    /// its place back to the end.
+    pub fn restore(&mut self, index: usize, leaf: Digest) {
+        match self.levels.first().and_then(|leaves| leaves.get(index)).copied() {
+            Some(moved) => {
+                self.push(moved);
+                self.update(index, leaf);
+            }
+            None => {
+                self.push(leaf);
+            }
+        }
+    }
+
+    pub fn leaf(&self, index: usize) -> Digest {
+        self.levels[0][index]
+    }
+
+    /// Recomputes the nodes on the path from the leaf at index to the root.
+    fn rehash(&mut self, mut index: usize) {
+        let mut level = 0;
+
+        while self.levels[level].len() > 1 {
+            let parent = index / 2;
+            let left = &self.levels[level][parent * 2];
+            let node = match self.levels[level].get(parent * 2 + 1) {
+                Some(right) => Digest::node(left, right),
+                None => *left,
+            };
+
+            if self.levels.len() == level + 1 {
+                self.levels.push(Vec::new());
+            }
+
+            let next = &mut self.levels[level + 1];
+
+            if parent < next.len() {
+                next[parent] = node;
+            } else {
+                next.push(node);
+            }
+
+            index = parent;
+            level += 1;
+        }
+
+        // The root level holds a single node
+        self.levels.truncate(level + 1);
+    }
+
+    /// Sibling hashes from the leaf at index up to the root.
+    pub fn proof(&self, mut index: usize) -> Vec<ProofStep> {
+        let mut steps = Vec::new();
+
+        for level in self.levels.iter().take(self.levels.len().saturating_sub(1)) {
+            let sibling = index ^ 1;
+
+            if let Some(hash) = level.get(sibling) {
+                steps.push(ProofStep {
+                    sibling: *hash,
+                    left: sibling < index,
+                });
+            }
+
+            index /= 2;
+        }
+
+        steps
+    }
+}
+
+/// Proof that a user record is committed to by a tree root.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InclusionProof {
+    pub certificate: String,
+    pub user: CommittedUser,
+    pub path: Vec<ProofStep>,
+}
+
+impl InclusionProof {
+    /// The root the proof leads to.
+    pub fn root(&self) -> Digest {
+        self.path.iter().fold(self.user.leaf(), |hash, step| {
+            if step.left {
+                Digest::node(&step.sibling, &hash)
+            } else {
+                Digest::node(&hash, &step.sibling)
+            }
+        })
+    }
+
+    pub fn verify(&self, root: &Digest) -> bool {
+        self.root() == *root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::user::ADMIN_ID;
+
+    fn leaves(count: u8) -> Vec<Digest> {
+        (0..count).map(|byte| Digest::from_bytes([byte; 32])).collect()
+    }
+
+    fn built(leaves: &[Digest]) -> MerkleTree {
+        let mut tree = MerkleTree::default();
+
+        for leaf in leaves.iter() {
+            tree.push(*leaf);
+        }
+
+        tree
+    }
+
+    /// Whether the proof of every leaf leads to the root.
+    fn proves(tree: &MerkleTree, leaves: &[Digest]) -> bool {
+        leaves.iter().enumerate().all(|(index, leaf)| {
+            let root = tree.proof(index).iter().fold(*leaf, |hash, step| {
+                if step.left {
+                    Digest::node(&step.sibling, &hash)
+                } else {
+                    Digest::node(&hash, &step.sibling)
+                }
+            });
+
+            root == tree.root()
+        })
+    }
+
+    #[test]
+    fn updates_match_a_rebuilt_tree() {
+        for count in 1..=9 {
+            let mut expected = leaves(count);
+            let mut tree = built(&expected);
+            assert!(proves(&tree, &expected));
+
+            for index in 0..expected.len() {
+                expected[index] = Digest::from_bytes([100 + index as u8; 32]);
+                tree.update(index, expected[index]);
+
+                assert_eq!(tree.root(), built(&expected).root());
+                assert!(proves(&tree, &expected));
+            }
+        }
+    }
+
+    #[test]
+    fn swap_removals_match_a_rebuilt_tree() {
+        for count in 1..=9 {
+            for index in 0..usize::from(count) {
+                let mut expected = leaves(count);
+                let mut tree = built(&expected);
+
+                expected.swap_remove(index);
+                tree.swap_remove(index);
+
+                assert_eq!(tree.root(), built(&expected).root());
+                assert!(proves(&tree, &expected));
+
+                // The tree keeps growing from its new size
+                expected.push(Digest::from_bytes([200; 32]));
+                tree.push(expected[expected.len() - 1]);
+                assert_eq!(tree.root(), built(&expected).root());
+            }
+        }
+
+        let mut tree = built(&leaves(1));
+        tree.swap_remove(0);
+        assert_eq!(tree.root(), Digest::default());
+    }
+
+    #[test]
+    fn restores_undo_swap_removals() {
+        for count in 1..=9 {
+            for index in 0..usize::from(count) {
+                let expected = leaves(count);
+                let mut tree = built(&expected);
+
+                tree.swap_remove(index);
+                tree.restore(index, expected[index]);
+
+                assert_eq!(tree.root(), built(&expected).root());
+                assert!(proves(&tree, &expected));
+            }
+        }
+    }
+
+    #[test]
+    fn leaves_commit_to_when_a_block_ends() {
+        let mut user = User::new(
+            "bob",
+            ADMIN_ID,
+            Role::Moderator,
+            Limits::NONE,
+            PublicKey::from_bytes([0; 32]),
+        );
+        user.blocked = true;
+        let for_good = CommittedUser::new(&user);
+
+        user.blocked_until = Some(2_000_000);
+        let temporary = CommittedUser::new(&user);
+
+        assert_eq!(temporary.blocked_until, Some(2_000_000));
+        assert_ne!(temporary.leaf(), for_good.leaf());
+
+        // Leaves of users blocked for good hash as before blocks could end
+        let json = serde_json::to_value(&for_good).unwrap();
+        assert!(json.get("blocked_until").is_none());
+    }
+}
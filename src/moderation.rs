@@ -0,0 +1,174 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// A threshold a tree acts on by itself once it is crossed, checked after
+/// every report and block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "rule", rename_all = "kebab-case")]
+pub enum Rule {
+    /// Blocks a user once as many reporters as given reported it directly.
+    BlockOnReports { reports: usize },
+    /// Suspends a parent's invite capability while more than the given
+    /// percentage of its children are blocked, for parents with at least
+    /// `min_children` children.
+    SuspendInvitesOnBlockedChildren {
+        percent: u8,
+        #[serde(default = "crate::policy::one")]
+        min_children: usize,
+    },
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rule::BlockOnReports { reports } => write!(f, "block on {reports} reports"),
+            Rule::SuspendInvitesOnBlockedChildren { percent, min_children } => {
+                write!(
+                    f,
+                    "suspend invites on more than {percent}% of at least {min_children} children blocked"
+                )
+            }
+        }
+    }
+}
+
+/// An action a tree took by itself.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum AutomaticAction {
+    Block {
+        user_id: String,
+    },
+    SuspendInvites {
+        user_id: String,
+    },
+    /// Lifts a suspension once its rule no longer holds.
+    RestoreInvites {
+        user_id: String,
+    },
+}
+
+impl fmt::Display for AutomaticAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AutomaticAction::Block { user_id } => write!(f, "blocked user {user_id}"),
+            AutomaticAction::SuspendInvites { user_id } => write!(f, "suspended invites of user {user_id}"),
+            AutomaticAction::RestoreInvites { user_id } => write!(f, "restored invites of user {user_id}"),
+        }
+    }
+}
+
+/// Entry of a tree's audit trail of automatic actions.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Unix time of the operation that triggered the action.
+    pub time: u64,
+    pub rule: Rule,
+    #[serde(flatten)]
+    pub action: AutomaticAction,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::Event;
+    use crate::history::BlockReason;
+    use crate::policy::Policy;
+    use crate::report::ReportReason;
+    use crate::testing::{Fixture, CERTIFICATE};
+    use crate::user::ADMIN_ID;
+
+    fn with_rules(users: &[(&str, &str)], rules: Vec<Rule>) -> Fixture {
+        let fixture = Fixture::with_users(users);
+        fixture
+            .submit(Event::SetPolicy {
+                certificate: CERTIFICATE.to_owned(),
+                policy: Policy {
+                    rules,
+                    ..Policy::default()
+                },
+            })
+            .unwrap();
+
+        fixture
+    }
+
+    fn report(fixture: &Fixture, user_id: &str, reporter: &str) {
+        fixture
+            .submit(Event::ReportUser {
+                certificate: CERTIFICATE.to_owned(),
+                user_id: user_id.to_owned(),
+                reporter: reporter.to_owned(),
+                reason: ReportReason::Spam,
+                text: None,
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn users_are_blocked_once_enough_reporters_reported_them() {
+        let rule = Rule::BlockOnReports { reports: 2 };
+        let fixture = with_rules(
+            &[("alice", ADMIN_ID), ("bob", ADMIN_ID), ("carol", ADMIN_ID)],
+            vec![rule],
+        );
+
+        report(&fixture, "carol", "alice");
+        report(&fixture, "carol", "alice");
+        assert!(fixture.permitted("carol"));
+
+        report(&fixture, "carol", "bob");
+        assert!(!fixture.permitted("carol"));
+
+        let carol = fixture.database.user_info("carol", CERTIFICATE).unwrap().user;
+        assert_eq!(carol.block_history.last().unwrap().reason, BlockReason::Rule);
+
+        let audit = fixture.database.audit_trail(CERTIFICATE).unwrap();
+        assert_eq!(audit.len(), 1);
+        assert_eq!(audit[0].rule, rule);
+        assert_eq!(
+            audit[0].action,
+            AutomaticAction::Block {
+                user_id: "carol".to_owned()
+            }
+        );
+    }
+
+    #[test]
+    fn restored_invites_name_the_rule_that_suspended_them() {
+        let strict = Rule::SuspendInvitesOnBlockedChildren {
+            percent: 50,
+            min_children: 1,
+        };
+        let lenient = Rule::SuspendInvitesOnBlockedChildren {
+            percent: 0,
+            min_children: 2,
+        };
+        let fixture = with_rules(
+            &[("alice", ADMIN_ID), ("bob", "alice"), ("carol", "alice")],
+            vec![strict, lenient],
+        );
+
+        // One of two children blocked exceeds the second rule only
+        fixture.block("bob", "alice", Some(60)).unwrap();
+        fixture.clock.advance(60);
+        report(&fixture, "carol", ADMIN_ID);
+
+        let user_id = "alice".to_owned();
+        let audit = fixture.database.audit_trail(CERTIFICATE).unwrap();
+        let actions: Vec<_> = audit.iter().map(|entry| (entry.rule, entry.action.clone())).collect();
+        assert_eq!(
+            actions,
+            [
+                (
+                    lenient,
+                    AutomaticAction::SuspendInvites {
+                        user_id: user_id.clone()
+                    }
+                ),
+                (lenient, AutomaticAction::RestoreInvites { user_id }),
+            ]
+        );
+    }
+}
@@ -0,0 +1,65 @@
+//! Names of the enums users pass on the command line and in queries.
+
+/// Implements `ALL`, `Display` and `FromStr` for a fieldless enum from the
+/// names of its variants, which must be the names serde gives them, and
+/// declares the error parsing them returns.
+///
+/// ```text
+/// names! {
+///     /// Error parsing a role name.
+///     Role, ParseRoleError {
+///         Member => "member",
+///         CoAdmin => "co-admin",
+///     }
+/// }
+/// ```
+macro_rules! names {
+    (
+        $(#[$error_meta:meta])*
+        $name:ident, $error:ident {
+            $($variant:ident => $text:literal),+ $(,)?
+        }
+    ) => {
+        impl $name {
+            pub const ALL: [$name; [$($text),+].len()] = [$($name::$variant),+];
+        }
+
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                let name = match self {
+                    $($name::$variant => $text,)+
+                };
+
+                f.write_str(name)
+            }
+        }
+
+        $(#[$error_meta])*
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub struct $error;
+
+        impl std::fmt::Display for $error {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                let names = [$($text),+];
+                let (last, rest) = names.split_last().expect("enums have a variant");
+
+                write!(f, "expected one of {} or {last}", rest.join(", "))
+            }
+        }
+
+        impl std::error::Error for $error {}
+
+        impl std::str::FromStr for $name {
+            type Err = $error;
+
+            fn from_str(text: &str) -> Result<$name, $error> {
+                $name::ALL
+                    .into_iter()
+                    .find(|value| value.to_string() == text.trim())
+                    .ok_or($error)
+            }
+        }
+    };
+}
+
+pub(crate) use names;
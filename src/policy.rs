@@ -0,0 +1,246 @@
+use serde::{Deserialize, Serialize};
+
+use crate::moderation::Rule;
+
+/// Rules a certificate's admin sets for its user tree.
+///
+/// Every setting is omitted when it has its default, so that policies signed
+/// before a setting existed keep their signatures.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Policy {
+    #[serde(default, skip_serializing_if = "MovePolicy::is_default")]
+    pub moves: MovePolicy,
+    #[serde(default, skip_serializing_if = "ReportPolicy::is_default")]
+    pub reports: ReportPolicy,
+    /// Thresholds the tree acts on by itself, in order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<Rule>,
+    #[serde(default, skip_serializing_if = "AppealPolicy::is_default")]
+    pub appeals: AppealPolicy,
+}
+
+impl Policy {
+    pub fn is_default(&self) -> bool {
+        *self == Policy::default()
+    }
+}
+
+/// Consent a move needs besides the mover's, which must be the user itself
+/// or one that may moderate it, and the new parent's, which every move needs.
+/// Moves into the user's own subtree are never allowed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MovePolicy {
+    /// The new parent must have permission, the invite capability and room
+    /// for another child.
+    #[serde(default = "enabled")]
+    pub new_parent_permission: bool,
+    /// The user's current parent must approve.
+    #[serde(default)]
+    pub old_parent_approval: bool,
+    /// The moved user must approve.
+    #[serde(default)]
+    pub user_approval: bool,
+}
+
+impl Default for MovePolicy {
+    fn default() -> MovePolicy {
+        MovePolicy {
+            new_parent_permission: true,
+            old_parent_approval: false,
+            user_approval: false,
+        }
+    }
+}
+
+impl MovePolicy {
+    pub fn is_default(&self) -> bool {
+        *self == MovePolicy::default()
+    }
+}
+
+fn enabled() -> bool {
+    true
+}
+
+/// Who decides the appeals of blocked users, which go to the ancestors of
+/// their blocker that may moderate.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppealPolicy {
+    /// A user that reviews every appeal besides the ancestors and decides
+    /// it alone.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reviewer: Option<String>,
+    /// Number of reviewers that must accept an appeal, at most all of them.
+    #[serde(default = "one")]
+    pub quorum: usize,
+}
+
+impl Default for AppealPolicy {
+    fn default() -> AppealPolicy {
+        AppealPolicy {
+            reviewer: None,
+            quorum: 1,
+        }
+    }
+}
+
+impl AppealPolicy {
+    pub fn is_default(&self) -> bool {
+        *self == AppealPolicy::default()
+    }
+}
+
+pub(crate) fn one() -> usize {
+    1
+}
+
+/// How reports against a user weigh on its ancestors and fade over time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct ReportPolicy {
+    #[serde(default, skip_serializing_if = "Propagation::is_default")]
+    pub propagation: Propagation,
+    /// Seconds after which a report weighs half as much, none to never fade.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub half_life: Option<u64>,
+}
+
+impl ReportPolicy {
+    pub fn is_default(&self) -> bool {
+        *self == ReportPolicy::default()
+    }
+
+    /// Weight of a report the given number of seconds old.
+    pub fn age_weight(&self, age: u64) -> f64 {
+        match self.half_life {
+            Some(half_life) if half_life > 0 => 0.5f64.powf(age as f64 / half_life as f64),
+            _ => 1.0,
+        }
+    }
+}
+
+/// Ancestors a report against a user counts for, by their distance to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Propagation {
+    /// Every ancestor in full, the way reports counted before policies.
+    #[default]
+    All,
+    /// Only the user itself.
+    None,
+    /// The given number of nearest ancestors in full.
+    Levels(u32),
+    /// Every ancestor, weighted by the percentage, at most 100, to the power
+    /// of the distance.
+    Geometric(u8),
+}
+
+impl Propagation {
+    pub fn is_default(&self) -> bool {
+        *self == Propagation::default()
+    }
+
+    /// Weight of a report against a user for its ancestor at a distance, zero
+    /// for the ancestors it does not reach. Never grows with the distance.
+    pub fn weight(&self, distance: u32) -> f64 {
+        match *self {
+            _ if distance == 0 => 1.0,
+            Propagation::All => 1.0,
+            Propagation::None => 0.0,
+            Propagation::Levels(levels) if distance <= levels => 1.0,
+            Propagation::Levels(_) => 0.0,
+            Propagation::Geometric(percent) => (f64::from(percent.min(100)) / 100.0).powf(f64::from(distance)),
+        }
+    }
+
+    /// Whether a report against a user adds to the report count of its
+    /// ancestor at a distance, which it does while it weighs at least half
+    /// for the ancestor, so that the count is the sum of the rounded weights.
+    pub fn counts(&self, distance: u32) -> bool {
+        self.weight(distance) >= 0.5
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::Event;
+    use crate::report::ReportReason;
+    use crate::testing::{Fixture, CERTIFICATE};
+    use crate::user::ADMIN_ID;
+
+    /// A chain from the admin down to dave, reported by erin.
+    fn reported(reports: ReportPolicy) -> Fixture {
+        let fixture = Fixture::with_users(&[
+            ("alice", ADMIN_ID),
+            ("bob", "alice"),
+            ("carol", "bob"),
+            ("dave", "carol"),
+            ("erin", ADMIN_ID),
+        ]);
+        let policy = Policy {
+            reports,
+            ..Policy::default()
+        };
+
+        fixture
+            .submit(Event::SetPolicy {
+                certificate: CERTIFICATE.to_owned(),
+                policy,
+            })
+            .unwrap();
+        fixture
+            .submit(Event::ReportUser {
+                certificate: CERTIFICATE.to_owned(),
+                user_id: "dave".to_owned(),
+                reporter: "erin".to_owned(),
+                reason: ReportReason::Spam,
+                text: None,
+            })
+            .unwrap();
+
+        fixture
+    }
+
+    fn counts(fixture: &Fixture) -> Vec<u16> {
+        ["dave", "carol", "bob", "alice", ADMIN_ID]
+            .into_iter()
+            .map(|user_id| fixture.database.user_info(user_id, CERTIFICATE).unwrap().user.reports)
+            .collect()
+    }
+
+    #[test]
+    fn reports_count_for_the_ancestors_they_reach() {
+        let all = reported(ReportPolicy::default());
+        assert_eq!(counts(&all), [1, 1, 1, 1, 1]);
+
+        let levels = reported(ReportPolicy {
+            propagation: Propagation::Levels(2),
+            half_life: None,
+        });
+        assert_eq!(counts(&levels), [1, 1, 1, 0, 0]);
+
+        // Weights 1, 0.5, 0.25 and less count while at least half
+        let geometric = reported(ReportPolicy {
+            propagation: Propagation::Geometric(50),
+            half_life: None,
+        });
+        assert_eq!(counts(&geometric), [1, 1, 0, 0, 0]);
+
+        let score = geometric.database.user_info("bob", CERTIFICATE).unwrap().score;
+        assert_eq!(score.propagated, 0.25);
+    }
+
+    #[test]
+    fn reports_fade_with_their_half_life() {
+        let fixture = reported(ReportPolicy {
+            propagation: Propagation::None,
+            half_life: Some(3600),
+        });
+        fixture.clock.advance(7200);
+
+        let dave = fixture.database.user_info("dave", CERTIFICATE).unwrap();
+        assert_eq!(dave.score.direct, 0.25);
+        assert_eq!(dave.user.reports, 1);
+        assert_eq!(counts(&fixture)[1..], [0, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,370 @@
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::delegation::Limits;
+use crate::error::{Error, Result};
+use crate::event::{Event, SignedEvent};
+use crate::identity::PublicKey;
+use crate::tree::Orphans;
+use crate::user::ADMIN_ID;
+
+/// Chain of signed grants from the admin of a certificate down to a user.
+///
+/// Every grant is the signed event that placed a user under its parent, so a
+/// relying party holding only the admin's public key can check the chain
+/// offline, one hop at a time.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PermissionProof {
+    pub certificate: String,
+    pub user_id: String,
+    /// Grants ordered from the admin's child down to the user.
+    pub grants: Vec<SignedEvent>,
+}
+
+impl PermissionProof {
+    /// Verifies the chain of grants starting at the admin's key, rejecting it
+    /// if any user along the chain is revoked or any grant's limits expired
+    /// before the Unix time now. Returns the user's public key.
+    pub fn verify(&self, admin_key: &PublicKey, revoked: &BTreeSet<String>, now: u64) -> Result<PublicKey> {
+        // Users the grants so far lead through, with their keys
+        let mut chain = vec![(ADMIN_ID, *admin_key)];
+
+        if revoked.contains(ADMIN_ID) {
+            return Err(self.invalid("revoked user in chain"));
+        }
+
+        for (position, grant) in self.grants.iter().enumerate() {
+            // A removal grants several children, and the chain goes on with
+            // the one that makes the next grant
+            let next = match self.grants.get(position + 1) {
+                Some(next) => grantor(&next.event),
+                None => Some(self.user_id.as_str()),
+            };
+
+            let (user_id, public_key) = self.verify_grant(grant, &chain, next)?;
+
+            if revoked.contains(user_id) {
+                return Err(self.invalid("revoked user in chain"));
+            }
+
+            if limits(&grant.event).is_expired(now) {
+                return Err(self.invalid("expired grant in chain"));
+            }
+
+            chain.push((user_id, public_key));
+        }
+
+        match chain.last() {
+            Some((user_id, public_key)) if *user_id == self.user_id => Ok(*public_key),
+            _ => Err(self.invalid("chain does not end at the user")),
+        }
+    }
+
+    /// Checks that the last user of the chain, or for a removal one of the
+    /// chain, signed a grant of the certificate to a child, returning the
+    /// child's id and key.
+    fn verify_grant<'a>(
+        &self,
+        grant: &'a SignedEvent,
+        chain: &[(&str, PublicKey)],
+        next: Option<&str>,
+    ) -> Result<(&'a str, PublicKey)> {
+        let event = &grant.event;
+        let (grantor_id, grantor_key) = chain[chain.len() - 1];
+        let authorization = grant
+            .authorization
+            .as_ref()
+            .ok_or_else(|| self.invalid("unsigned grant"))?;
+
+        if event.certificate() != self.certificate {
+            return Err(self.invalid("grant of another certificate"));
+        }
+
+        let parent = grantor(event).ok_or_else(|| self.invalid("event is not a grant"))?;
+
+        if parent != grantor_id {
+            return Err(self.invalid("grant not made by the previous user in the chain"));
+        }
+
+        let message = event.signing_bytes(authorization.nonce);
+        let (user_id, public_key) = match event {
+            Event::AddUser {
+                user_id, public_key, ..
+            }
+            | Event::UnblockUser {
+                user_id, public_key, ..
+            }
+            | Event::MoveUser {
+                user_id, public_key, ..
+            }
+            | Event::ReviewAppeal {
+                user_id, public_key, ..
+            } => (user_id, public_key),
+            Event::RemoveUser { children, .. } => next
+                .and_then(|next| children.get_key_value(next))
+                .ok_or_else(|| self.invalid("removal does not move the user"))?,
+            Event::RedeemInvitation {
+                user_id,
+                public_key,
+                invitation,
+            } => {
+                if !invitation.verify(&grantor_key) {
+                    return Err(self.invalid("invitation not signed by the parent"));
+                }
+
+                if invitation
+                    .invitation
+                    .user_id
+                    .as_deref()
+                    .is_some_and(|intended| intended != user_id)
+                {
+                    return Err(self.invalid("invitation issued for another user"));
+                }
+
+                // The redeeming user signs the event with the key it joins with
+                if !public_key.verify(&message, &authorization.signature) {
+                    return Err(self.invalid("redemption not signed by the user"));
+                }
+
+                return Ok((user_id, *public_key));
+            }
+            _ => return Err(self.invalid("event is not a grant")),
+        };
+
+        // The new parent of a removal or any of its ancestors may sign or
+        // approve it, and the new parent of a move may approve it instead of
+        // signing it
+        let signers = match event {
+            Event::RemoveUser { .. } => chain,
+            _ => &chain[chain.len() - 1..],
+        };
+        let signed = signers
+            .iter()
+            .any(|(signer, key)| event.actor() == Some(signer) && key.verify(&message, &authorization.signature));
+        let approved = matches!(event, Event::MoveUser { .. } | Event::RemoveUser { .. })
+            && grant.approvals.iter().any(|approval| {
+                signers
+                    .iter()
+                    .any(|(signer, key)| approval.user_id == *signer && key.verify(&message, &approval.signature))
+            });
+
+        if !signed && !approved {
+            return Err(self.invalid("grant not signed by the parent"));
+        }
+
+        Ok((user_id, *public_key))
+    }
+
+    fn invalid(&self, reason: &'static str) -> Error {
+        Error::InvalidProof {
+            user_id: self.user_id.clone(),
+            reason,
+        }
+    }
+}
+
+/// Limits a grant places on the granted user's subtree.
+fn limits(event: &Event) -> Limits {
+    match event {
+        Event::AddUser { limits, .. } | Event::MoveUser { limits, .. } => *limits,
+        Event::RedeemInvitation { invitation, .. } => invitation.invitation.limits,
+        _ => Limits::NONE,
+    }
+}
+
+/// The user an event grants users to, if it is a grant.
+fn grantor(event: &Event) -> Option<&str> {
+    match event {
+        Event::AddUser { parent, .. } => Some(parent),
+        Event::UnblockUser { unblocker, .. } => Some(unblocker),
+        Event::MoveUser { new_parent, .. } => Some(new_parent),
+        Event::RedeemInvitation { invitation, .. } => Some(&invitation.invitation.parent),
+        Event::ReviewAppeal {
+            reviewer,
+            accept: true,
+            keep_parent: false,
+            ..
+        } => Some(reviewer),
+        Event::RemoveUser {
+            orphans: Orphans::Adopt { adopter },
+            ..
+        } => Some(adopter),
+        Event::RemoveUser {
+            orphans: Orphans::ReattachToParent,
+            parent,
+            ..
+        } if !parent.is_empty() => Some(parent),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::role::Role;
+    use crate::testing::{Fixture, CERTIFICATE};
+
+    fn proof(fixture: &Fixture, user_id: &str) -> PermissionProof {
+        fixture.database.permission_proof(user_id, CERTIFICATE).unwrap()
+    }
+
+    #[test]
+    fn proofs_verify_from_the_admins_key() {
+        let fixture = Fixture::with_users(&[("alice", ADMIN_ID), ("bob", "alice")]);
+        let admin_key = fixture.key(ADMIN_ID).public_key();
+        let now = fixture.database.now();
+        let proof = proof(&fixture, "bob");
+
+        assert_eq!(proof.grants.len(), 2);
+        assert_eq!(
+            proof.verify(&admin_key, &BTreeSet::new(), now).unwrap(),
+            fixture.key("bob").public_key()
+        );
+        assert!(proof
+            .verify(&fixture.key("alice").public_key(), &BTreeSet::new(), now)
+            .is_err());
+        assert!(proof
+            .verify(&admin_key, &BTreeSet::from(["alice".to_owned()]), now)
+            .is_err());
+
+        let mut forged = proof.clone();
+        forged.user_id = "alice".to_owned();
+        assert!(forged.verify(&admin_key, &BTreeSet::new(), now).is_err());
+    }
+
+    #[test]
+    fn moves_approved_by_the_new_parent_grant_the_user() {
+        let fixture = Fixture::with_users(&[("alice", ADMIN_ID), ("bob", "alice"), ("carol", ADMIN_ID)]);
+        let admin_key = fixture.key(ADMIN_ID).public_key();
+        let now = fixture.database.now();
+
+        let event = fixture.database.move_event("bob", "carol", "bob", CERTIFICATE);
+        fixture.submit_approved(event, &["carol"]).unwrap();
+
+        let proof = proof(&fixture, "bob");
+        assert!(matches!(&proof.grants[1].event, Event::MoveUser { new_parent, .. } if new_parent == "carol"));
+        proof.verify(&admin_key, &BTreeSet::new(), now).unwrap();
+
+        // Another user's approval does not grant the user to carol
+        let mut forged = proof.clone();
+        forged.grants[1].approvals[0].user_id = "alice".to_owned();
+        assert!(forged.verify(&admin_key, &BTreeSet::new(), now).is_err());
+    }
+
+    fn remove(fixture: &Fixture, user_id: &str, remover: &str, orphans: Orphans) {
+        let event = fixture.database.remove_event(user_id, remover, orphans, CERTIFICATE);
+        fixture.submit(event).unwrap();
+    }
+
+    #[test]
+    fn removals_grant_the_children_to_their_new_parent() {
+        let fixture = Fixture::with_users(&[("alice", ADMIN_ID), ("bob", "alice"), ("carol", "bob"), ("dave", "bob")]);
+        let admin_key = fixture.key(ADMIN_ID).public_key();
+        let now = fixture.database.now();
+
+        remove(&fixture, "bob", "alice", Orphans::ReattachToParent);
+
+        for user_id in ["carol", "dave"] {
+            let proof = proof(&fixture, user_id);
+            assert!(matches!(proof.grants[1].event, Event::RemoveUser { .. }));
+            assert_eq!(
+                proof.verify(&admin_key, &BTreeSet::new(), now).unwrap(),
+                fixture.key(user_id).public_key()
+            );
+        }
+
+        // The admin removing alice is an ancestor of the adopter
+        remove(
+            &fixture,
+            "alice",
+            ADMIN_ID,
+            Orphans::Adopt {
+                adopter: ADMIN_ID.to_owned(),
+            },
+        );
+        proof(&fixture, "carol")
+            .verify(&admin_key, &BTreeSet::new(), now)
+            .unwrap();
+    }
+
+    #[test]
+    fn removals_need_the_new_parents_consent_and_grant_the_children_to_it() {
+        let fixture = Fixture::with_users(&[("alice", ADMIN_ID), ("bob", "alice"), ("carol", ADMIN_ID)]);
+        let admin_key = fixture.key(ADMIN_ID).public_key();
+        let now = fixture.database.now();
+        let orphans = Orphans::Adopt {
+            adopter: "carol".to_owned(),
+        };
+
+        // alice is no ancestor of carol, which adopts bob
+        let event = fixture.database.remove_event("alice", "alice", orphans, CERTIFICATE);
+        let removed = fixture.submit(event.clone());
+        assert!(matches!(removed, Err(Error::MissingApproval { user_id }) if user_id == "carol"));
+
+        fixture.submit_approved(event, &["carol"]).unwrap();
+        let proof = proof(&fixture, "bob");
+        assert!(matches!(proof.grants[1].event, Event::RemoveUser { .. }));
+        proof.verify(&admin_key, &BTreeSet::new(), now).unwrap();
+
+        // Another user's approval does not grant the children to carol
+        let mut forged = proof.clone();
+        forged.grants[1].approvals[0].user_id = "bob".to_owned();
+        assert!(forged.verify(&admin_key, &BTreeSet::new(), now).is_err());
+    }
+
+    #[test]
+    fn accepted_appeals_grant_the_user_to_the_reviewer() {
+        let fixture = Fixture::with_users(&[("alice", ADMIN_ID), ("bob", "alice")]);
+        let admin_key = fixture.key(ADMIN_ID).public_key();
+        let now = fixture.database.now();
+
+        fixture.block("bob", "alice", None).unwrap();
+        fixture
+            .submit(Event::FileAppeal {
+                certificate: CERTIFICATE.to_owned(),
+                user_id: "bob".to_owned(),
+                statement: "a friend posted the ads".to_owned(),
+            })
+            .unwrap();
+        fixture
+            .submit(fixture.database.review_event(0, ADMIN_ID, true, false, CERTIFICATE))
+            .unwrap();
+
+        let proof = proof(&fixture, "bob");
+        assert!(matches!(
+            proof.grants[..],
+            [SignedEvent {
+                event: Event::ReviewAppeal { .. },
+                ..
+            }]
+        ));
+        proof.verify(&admin_key, &BTreeSet::new(), now).unwrap();
+    }
+
+    #[test]
+    fn proofs_through_expired_delegations_fail() {
+        let mut fixture = Fixture::with_users(&[("carol", ADMIN_ID)]);
+        let admin_key = fixture.key(ADMIN_ID).public_key();
+        let now = fixture.database.now();
+        let limits = Limits {
+            max_children: None,
+            valid_until: Some(now + 60),
+        };
+        fixture.add_limited("alice", ADMIN_ID, Role::Moderator, limits).unwrap();
+        fixture.add("bob", "alice", Role::Member).unwrap();
+
+        let through_alice = proof(&fixture, "bob");
+        through_alice.verify(&admin_key, &BTreeSet::new(), now + 60).unwrap();
+        let expired = through_alice.verify(&admin_key, &BTreeSet::new(), now + 61);
+        assert!(matches!(expired, Err(Error::InvalidProof { reason, .. }) if reason == "expired grant in chain"));
+
+        // A move keeps the expiry in the grant
+        let event = fixture.database.move_event("bob", "carol", "bob", CERTIFICATE);
+        fixture.submit_approved(event, &["carol"]).unwrap();
+        let through_carol = proof(&fixture, "bob");
+        assert_eq!(through_carol.grants.len(), 2);
+        through_carol.verify(&admin_key, &BTreeSet::new(), now + 60).unwrap();
+        assert!(through_carol.verify(&admin_key, &BTreeSet::new(), now + 61).is_err());
+    }
+}
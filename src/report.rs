@@ -0,0 +1,108 @@
+use serde::{Deserialize, Serialize};
+
+use crate::names::names;
+
+/// Category of misbehavior a user is reported for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReportReason {
+    Spam,
+    Abuse,
+    Impersonation,
+    Fraud,
+    Other,
+}
+
+names! {
+    /// Error parsing a report reason.
+    ReportReason, ParseReasonError {
+        Spam => "spam",
+        Abuse => "abuse",
+        Impersonation => "impersonation",
+        Fraud => "fraud",
+        Other => "other",
+    }
+}
+
+/// A user's report against another user. A user holds at most one report by
+/// each reporter, with the latest reason and text it gave.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Report {
+    pub reporter: String,
+    pub reason: ReportReason,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// Unix time the reporter first reported the user at.
+    pub time: u64,
+}
+
+/// A user's report score, with every report weighted by its age.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+pub struct ReportScore {
+    /// From the reports against the user itself.
+    pub direct: f64,
+    /// From the reports against the user and the descendants the tree's
+    /// propagation reaches it from.
+    pub propagated: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::Event;
+    use crate::testing::{Fixture, CERTIFICATE};
+    use crate::user::{User, ADMIN_ID};
+
+    fn report(fixture: &Fixture, user_id: &str, reporter: &str, reason: ReportReason, text: &str) {
+        fixture
+            .submit(Event::ReportUser {
+                certificate: CERTIFICATE.to_owned(),
+                user_id: user_id.to_owned(),
+                reporter: reporter.to_owned(),
+                reason,
+                text: Some(text.to_owned()),
+            })
+            .unwrap();
+    }
+
+    fn user(fixture: &Fixture, user_id: &str) -> User {
+        fixture.database.user_info(user_id, CERTIFICATE).unwrap().user
+    }
+
+    #[test]
+    fn repeated_reports_keep_their_first_time() {
+        let fixture = Fixture::with_users(&[("alice", ADMIN_ID), ("bob", "alice"), ("carol", ADMIN_ID)]);
+        let start = fixture.database.now();
+
+        report(&fixture, "bob", "carol", ReportReason::Spam, "posts ads");
+        fixture.clock.advance(100);
+        report(&fixture, "bob", "carol", ReportReason::Fraud, "sells fakes");
+
+        let bob = user(&fixture, "bob");
+        assert_eq!(
+            bob.report_log,
+            [Report {
+                reporter: "carol".to_owned(),
+                reason: ReportReason::Fraud,
+                text: Some("sells fakes".to_owned()),
+                time: start,
+            }]
+        );
+        assert_eq!(bob.reports, 1);
+        assert_eq!(user(&fixture, "alice").reports, 1);
+    }
+
+    #[test]
+    fn reports_count_each_reporter_once() {
+        let fixture = Fixture::with_users(&[("alice", ADMIN_ID), ("bob", "alice"), ("carol", ADMIN_ID)]);
+
+        report(&fixture, "bob", "carol", ReportReason::Spam, "posts ads");
+        report(&fixture, "bob", "alice", ReportReason::Abuse, "insults");
+
+        assert_eq!(user(&fixture, "bob").reports, 2);
+        assert_eq!(user(&fixture, "alice").reports, 2);
+        assert_eq!(user(&fixture, ADMIN_ID).reports, 2);
+        assert_eq!(user(&fixture, "carol").reports, 0);
+        assert_eq!("fraud".parse(), Ok(ReportReason::Fraud));
+    }
+}
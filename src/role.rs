@@ -0,0 +1,237 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::names::names;
+
+/// A single right a user can hold in a certificate tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    /// Has permission in the certificate.
+    View,
+    /// May add children, directly or through invitations.
+    Invite,
+    /// May block its children and unblock users into its subtree.
+    Moderate,
+    /// May block any descendant, not only its children.
+    Administer,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 4] = [
+        Capability::View,
+        Capability::Invite,
+        Capability::Moderate,
+        Capability::Administer,
+    ];
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Capability::View => "view",
+            Capability::Invite => "invite",
+            Capability::Moderate => "moderate",
+            Capability::Administer => "administer",
+        };
+
+        f.write_str(name)
+    }
+}
+
+/// Set of capabilities, written as a list of their names.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(from = "Vec<Capability>", into = "Vec<Capability>")]
+pub struct Capabilities(u8);
+
+impl Capabilities {
+    /// The capabilities of a user without permission.
+    pub const NONE: Capabilities = Capabilities(0);
+
+    pub fn all() -> Capabilities {
+        Capability::ALL.into_iter().collect()
+    }
+
+    pub fn contains(&self, capability: Capability) -> bool {
+        self.0 & capability.bit() != 0
+    }
+
+    pub fn insert(&mut self, capability: Capability) {
+        self.0 |= capability.bit();
+    }
+
+    pub fn remove(&mut self, capability: Capability) {
+        self.0 &= !capability.bit();
+    }
+
+    /// Capabilities held by both sets.
+    pub fn intersection(self, other: Capabilities) -> Capabilities {
+        Capabilities(self.0 & other.0)
+    }
+
+    /// Capabilities of self that other does not hold.
+    pub fn difference(self, other: Capabilities) -> Capabilities {
+        Capabilities(self.0 & !other.0)
+    }
+
+    pub fn is_subset(&self, other: &Capabilities) -> bool {
+        self.difference(*other).is_empty()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Capability> + '_ {
+        Capability::ALL
+            .into_iter()
+            .filter(|capability| self.contains(*capability))
+    }
+}
+
+impl FromIterator<Capability> for Capabilities {
+    fn from_iter<I: IntoIterator<Item = Capability>>(capabilities: I) -> Capabilities {
+        let mut set = Capabilities::NONE;
+
+        for capability in capabilities {
+            set.insert(capability);
+        }
+
+        set
+    }
+}
+
+impl From<Vec<Capability>> for Capabilities {
+    fn from(capabilities: Vec<Capability>) -> Capabilities {
+        capabilities.into_iter().collect()
+    }
+}
+
+impl From<Capabilities> for Vec<Capability> {
+    fn from(capabilities: Capabilities) -> Vec<Capability> {
+        capabilities.iter().collect()
+    }
+}
+
+impl fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<String> = self.iter().map(|capability| capability.to_string()).collect();
+        write!(f, "[{}]", names.join(", "))
+    }
+}
+
+impl fmt::Debug for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Capabilities({self})")
+    }
+}
+
+/// Named level of capabilities a parent grants a child.
+///
+/// Every role holds the capabilities of the roles before it. Users are
+/// members unless their parent names a more privileged role.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Role {
+    #[default]
+    Member,
+    Inviter,
+    Moderator,
+    CoAdmin,
+}
+
+impl Role {
+    pub fn capabilities(self) -> Capabilities {
+        Capability::ALL[..self as usize + 1].iter().copied().collect()
+    }
+
+    /// The most privileged role holding no more than the capabilities.
+    pub fn within(capabilities: Capabilities) -> Role {
+        Role::ALL
+            .into_iter()
+            .rev()
+            .find(|role| role.capabilities().is_subset(&capabilities))
+            .unwrap_or(Role::Member)
+    }
+
+    /// The role of users added before roles existed, which events and
+    /// stored users omit so that their signatures stay valid.
+    pub fn legacy() -> Role {
+        Role::Moderator
+    }
+
+    pub fn is_legacy(&self) -> bool {
+        *self == Role::legacy()
+    }
+}
+
+names! {
+    /// Error parsing a role name.
+    Role, ParseRoleError {
+        Member => "member",
+        Inviter => "inviter",
+        Moderator => "moderator",
+        CoAdmin => "co-admin",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::event::Event;
+    use crate::testing::Fixture;
+    use crate::user::ADMIN_ID;
+
+    #[test]
+    fn roles_hold_the_capabilities_of_the_roles_before_them() {
+        assert_eq!(Role::Member.capabilities(), [Capability::View].into_iter().collect());
+        assert!(Role::Inviter.capabilities().contains(Capability::Invite));
+        assert!(!Role::Inviter.capabilities().contains(Capability::Moderate));
+        assert_eq!(Role::CoAdmin.capabilities(), Capabilities::all());
+        assert_eq!("co-admin".parse(), Ok(Role::CoAdmin));
+    }
+
+    #[test]
+    fn users_are_members_unless_a_role_is_named() {
+        let mut fixture = Fixture::new();
+        fixture.add("alice", ADMIN_ID, Role::default()).unwrap();
+
+        let added = fixture.add("bob", "alice", Role::default()).unwrap_err();
+        assert!(matches!(
+            added.root_cause(),
+            Error::MissingCapability {
+                capability: Capability::Invite,
+                ..
+            }
+        ));
+
+        // A parent grants at most its own capabilities
+        fixture.add("carol", ADMIN_ID, Role::Inviter).unwrap();
+        assert!(fixture.add("dave", "carol", Role::Moderator).is_err());
+        fixture.add("dave", "carol", Role::Member).unwrap();
+    }
+
+    #[test]
+    fn events_without_a_role_keep_the_legacy_role() {
+        let legacy = r#"{"type": "add_user", "certificate": "post", "user_id": "alice", "parent": "admin",
+            "public_key": "0000000000000000000000000000000000000000000000000000000000000000"}"#;
+
+        let event: Event = serde_json::from_str(legacy).unwrap();
+        assert!(matches!(
+            event,
+            Event::AddUser {
+                role: Role::Moderator,
+                ..
+            }
+        ));
+        assert_eq!(serde_json::to_value(&event).unwrap().get("role"), None);
+
+        assert_eq!(serde_json::to_value(Role::default()).unwrap(), "member");
+    }
+}
@@ -0,0 +1,735 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::database::Database;
+use crate::delegation::Limits;
+use crate::error::{Error, ErrorKind};
+use crate::event::{Approval, Authorization, EventLog, SignedEvent};
+use crate::history::BlockReason;
+use crate::identity::PublicKey;
+use crate::invitation::SignedInvitation;
+use crate::policy::Policy;
+use crate::report::ReportReason;
+use crate::role::Role;
+use crate::storage::Storage;
+use crate::tree::Orphans;
+
+/// Address the server binds to unless told otherwise.
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:8080";
+
+/// Largest request body the server accepts.
+const MAX_BODY: usize = 1 << 20;
+
+/// Longest request line or header line the server accepts, line break
+/// included.
+const MAX_LINE: usize = 8 << 10;
+
+/// Number of connections handled at once. Further connections wait to be
+/// accepted.
+const WORKERS: usize = 16;
+
+/// Time a client has in all to send its request, and then to take the
+/// response, before its connection is dropped.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A response to an HTTP request, with a JSON body.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub body: Value,
+}
+
+impl Response {
+    fn new(status: u16, body: Value) -> Response {
+        Response { status, body }
+    }
+
+    fn error(status: u16, message: &str) -> Response {
+        Response::new(status, json!({ "error": message }))
+    }
+
+    fn from_error(error: &Error) -> Response {
+        let status = match error.kind() {
+            ErrorKind::NotFound => 404,
+            ErrorKind::Conflict => 409,
+            ErrorKind::Unauthorized => 403,
+            ErrorKind::Storage => 500,
+        };
+
+        Response::error(status, &error.full_message())
+    }
+}
+
+/// A connection that fails reads and writes once its deadline passed, so
+/// that a client sending or taking a byte at a time can not hold a worker.
+struct Deadline {
+    stream: TcpStream,
+    deadline: Instant,
+}
+
+impl Deadline {
+    fn new(stream: TcpStream) -> Deadline {
+        Deadline {
+            stream,
+            deadline: Instant::now() + TIMEOUT,
+        }
+    }
+
+    /// The time left, or an error once there is none.
+    fn left(&self) -> io::Result<Duration> {
+        let left = self.deadline.saturating_duration_since(Instant::now());
+
+        if left.is_zero() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "connection deadline passed"));
+        }
+
+        Ok(left)
+    }
+}
+
+impl Read for Deadline {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.set_read_timeout(Some(self.left()?))?;
+        self.stream.read(buf)
+    }
+}
+
+impl Write for Deadline {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.set_write_timeout(Some(self.left()?))?;
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+/// A parsed HTTP request.
+struct Request {
+    method: String,
+    path: String,
+    body: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+struct CreateTree {
+    certificate: String,
+    admin_key: PublicKey,
+    #[serde(flatten)]
+    authorization: Authorization,
+}
+
+#[derive(Deserialize)]
+struct AddUser {
+    user_id: String,
+    parent: String,
+    #[serde(default)]
+    role: Role,
+    #[serde(default)]
+    limits: Limits,
+    public_key: PublicKey,
+    #[serde(flatten)]
+    authorization: Authorization,
+}
+
+#[derive(Deserialize)]
+struct Remove {
+    remover: String,
+    #[serde(default = "reattach_to_parent")]
+    orphans: Orphans,
+    #[serde(default)]
+    approvals: Vec<Approval>,
+    #[serde(flatten)]
+    authorization: Authorization,
+}
+
+fn reattach_to_parent() -> Orphans {
+    Orphans::ReattachToParent
+}
+
+#[derive(Deserialize)]
+struct Move {
+    new_parent: String,
+    mover: String,
+    #[serde(default)]
+    approvals: Vec<Approval>,
+    #[serde(flatten)]
+    authorization: Authorization,
+}
+
+#[derive(Deserialize)]
+struct SetPolicy {
+    policy: Policy,
+    #[serde(flatten)]
+    authorization: Authorization,
+}
+
+#[derive(Deserialize)]
+struct Transact {
+    events: Vec<SignedEvent>,
+}
+
+#[derive(Deserialize)]
+struct Redeem {
+    token: SignedInvitation,
+    user_id: String,
+    public_key: PublicKey,
+    #[serde(flatten)]
+    authorization: Authorization,
+}
+
+#[derive(Deserialize)]
+struct Report {
+    reporter: String,
+    reason: ReportReason,
+    #[serde(default)]
+    text: Option<String>,
+    #[serde(flatten)]
+    authorization: Authorization,
+}
+
+#[derive(Deserialize)]
+struct Block {
+    blocker: String,
+    #[serde(default)]
+    duration: Option<u64>,
+    #[serde(default)]
+    reason: BlockReason,
+    #[serde(default)]
+    text: Option<String>,
+    #[serde(flatten)]
+    authorization: Authorization,
+}
+
+#[derive(Deserialize)]
+struct Unblock {
+    unblocker: String,
+    #[serde(default)]
+    reason: BlockReason,
+    #[serde(default)]
+    text: Option<String>,
+    #[serde(flatten)]
+    authorization: Authorization,
+}
+
+#[derive(Deserialize)]
+struct FileAppeal {
+    statement: String,
+    #[serde(flatten)]
+    authorization: Authorization,
+}
+
+#[derive(Deserialize)]
+struct ReviewAppeal {
+    reviewer: String,
+    accept: bool,
+    #[serde(default)]
+    keep_parent: bool,
+    #[serde(flatten)]
+    authorization: Authorization,
+}
+
+/// HTTP/JSON API over a database.
+///
+/// | Method | Path                                   | Body                                   |
+/// |--------|----------------------------------------|----------------------------------------|
+/// | POST   | `/trees`                               | `{"certificate", "admin_key"}`         |
+/// | GET    | `/trees/{cert}`                        |                                        |
+/// | POST   | `/trees/{cert}/users`                  | `{"user_id", "parent", "role"?, "limits"?, "public_key"}` |
+/// | GET    | `/trees/{cert}/users/{id}`             |                                        |
+/// | GET    | `/trees/{cert}/users/{id}/permission`  |                                        |
+/// | GET    | `/trees/{cert}/users/{id}/proof`       |                                        |
+/// | GET    | `/trees/{cert}/users/{id}/inclusion`   |                                        |
+/// | GET    | `/trees/{cert}/revocations`            |                                        |
+/// | GET    | `/trees/{cert}/audit`                  |                                        |
+/// | GET    | `/trees/{cert}/root`                   |                                        |
+/// | POST   | `/trees/{cert}/users/{id}/report`      | `{"reporter", "reason", "text"?}`      |
+/// | POST   | `/trees/{cert}/users/{id}/block`       | `{"blocker", "duration"?, "reason"?, "text"?}` |
+/// | POST   | `/trees/{cert}/users/{id}/unblock`     | `{"unblocker", "reason"?, "text"?}`    |
+/// | POST   | `/trees/{cert}/users/{id}/remove`      | `{"remover", "orphans"?, "approvals"?}` |
+/// | POST   | `/trees/{cert}/users/{id}/move`        | `{"new_parent", "mover", "approvals"?}` |
+/// | POST   | `/trees/{cert}/users/{id}/appeal`      | `{"statement"}`                        |
+/// | GET    | `/trees/{cert}/appeals`                |                                        |
+/// | GET    | `/trees/{cert}/appeals/{appeal}`       |                                        |
+/// | POST   | `/trees/{cert}/appeals/{appeal}/review` | `{"reviewer", "accept", "keep_parent"?}` |
+/// | POST   | `/trees/{cert}/policy`                 | `{"policy"}`                           |
+/// | POST   | `/trees/{cert}/transactions`           | `{"events"}`                           |
+/// | POST   | `/invitations/redeem`                  | `{"token", "user_id", "public_key"}`   |
+///
+/// Every body of a signed operation also carries the acting user's `nonce`
+/// and its hex `signature` over the operation's [`Event`](crate::Event). A
+/// transaction lists [`SignedEvent`]s, applied all or none.
+pub struct Server<S: Storage, L: EventLog> {
+    database: Database<S, L>,
+}
+
+impl<S, L> Server<S, L>
+where
+    S: Storage + Send + 'static,
+    L: EventLog + Send + 'static,
+{
+    pub fn new(database: Database<S, L>) -> Server<S, L> {
+        Server { database }
+    }
+
+    /// Accepts connections forever, handing each one to a fixed pool of
+    /// worker threads. Accepting waits while every worker is busy.
+    pub fn serve(self, listener: TcpListener) -> std::io::Result<()> {
+        let server = Arc::new(self);
+        let (sender, receiver) = mpsc::sync_channel::<TcpStream>(0);
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        for _ in 0..WORKERS {
+            let server = Arc::clone(&server);
+            let receiver = Arc::clone(&receiver);
+
+            thread::spawn(move || server.work(&receiver));
+        }
+
+        for stream in listener.incoming() {
+            if sender.send(stream?).is_err() {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handles connections until the server stops accepting them.
+    fn work(&self, receiver: &Mutex<Receiver<TcpStream>>) {
+        loop {
+            let stream = receiver.lock().unwrap_or_else(PoisonError::into_inner).recv();
+
+            match stream {
+                Ok(stream) => {
+                    let _ = self.handle_connection(stream);
+                }
+                Err(_) => return,
+            }
+        }
+    }
+
+    fn handle_connection(&self, stream: TcpStream) -> io::Result<()> {
+        let mut reader = BufReader::new(Deadline::new(stream.try_clone()?));
+
+        let response = match read_request(&mut reader)? {
+            Ok(request) => self.handle(&request.method, &request.path, &request.body),
+            Err(response) => response,
+        };
+
+        write_response(Deadline::new(stream), &response)
+    }
+
+    /// Routes a request to its tree operation.
+    pub fn handle(&self, method: &str, path: &str, body: &[u8]) -> Response {
+        let path = path.split('?').next().unwrap_or_default();
+
+        let segments: Option<Vec<String>> = path
+            .trim_matches('/')
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(percent_decode)
+            .collect();
+
+        let segments = match segments {
+            Some(segments) => segments,
+            None => return Response::error(400, "invalid percent-encoding in path"),
+        };
+        let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+
+        let database = &self.database;
+
+        let result = match (method, segments.as_slice()) {
+            ("POST", ["trees"]) => parse::<CreateTree>(body).map(|request| {
+                database
+                    .add_user_tree(&request.certificate, request.admin_key, request.authorization)
+                    .map(|()| Response::new(201, json!({ "certificate": request.certificate })))
+            }),
+            ("GET", ["trees", certificate]) => Ok(database
+                .tree_info(certificate)
+                .map(|info| Response::new(200, json!(info)))),
+            ("POST", ["trees", certificate, "users"]) => parse::<AddUser>(body).map(|request| {
+                database
+                    .add_user(
+                        &request.user_id,
+                        &request.parent,
+                        request.role,
+                        request.limits,
+                        request.public_key,
+                        certificate,
+                        request.authorization,
+                    )
+                    .map(|()| {
+                        Response::new(
+                            201,
+                            json!({ "certificate": certificate, "user_id": request.user_id, "parent": request.parent, "role": request.role }),
+                        )
+                    })
+            }),
+            ("GET", ["trees", certificate, "users", user_id]) => Ok(database
+                .user_info(user_id, certificate)
+                .map(|info| Response::new(200, json!(info)))),
+            ("GET", ["trees", certificate, "users", user_id, "permission"]) => {
+                Ok(database.check_user_permission(user_id, certificate).map(|permission| {
+                    Response::new(
+                        200,
+                        json!({
+                            "certificate": certificate,
+                            "user_id": user_id,
+                            "permission": permission.is_granted(),
+                            "capabilities": permission.capabilities,
+                            "limits": permission.limits,
+                        }),
+                    )
+                }))
+            }
+            ("GET", ["trees", certificate, "users", user_id, "proof"]) => Ok(database
+                .permission_proof(user_id, certificate)
+                .map(|proof| Response::new(200, json!(proof)))),
+            ("GET", ["trees", certificate, "users", user_id, "inclusion"]) => Ok(database
+                .inclusion_proof(user_id, certificate)
+                .map(|proof| Response::new(200, json!(proof)))),
+            ("GET", ["trees", certificate, "root"]) => Ok(database
+                .tree_root(certificate)
+                .map(|root| Response::new(200, json!({ "certificate": certificate, "root": root })))),
+            ("GET", ["trees", certificate, "audit"]) => Ok(database
+                .audit_trail(certificate)
+                .map(|audit| Response::new(200, json!({ "certificate": certificate, "audit": audit })))),
+            ("GET", ["trees", certificate, "revocations"]) => Ok(database
+                .revocation_list(certificate)
+                .map(|revoked| Response::new(200, json!({ "certificate": certificate, "revoked": revoked })))),
+            ("POST", ["trees", certificate, "users", user_id, "report"]) => parse::<Report>(body).map(|request| {
+                database
+                    .report_user(user_id, &request.reporter, request.reason, request.text.as_deref(), certificate, request.authorization)
+                    .map(|()| {
+                        Response::new(
+                            200,
+                            json!({ "certificate": certificate, "user_id": user_id, "reporter": request.reporter, "reason": request.reason }),
+                        )
+                    })
+            }),
+            ("POST", ["trees", certificate, "users", user_id, "block"]) => parse::<Block>(body).map(|request| {
+                let text = request.text.as_deref();
+
+                database
+                    .block_user(user_id, &request.blocker, request.duration, request.reason, text, certificate, request.authorization)
+                    .map(|()| {
+                        Response::new(
+                            200,
+                            json!({ "certificate": certificate, "user_id": user_id, "blocker": request.blocker, "reason": request.reason }),
+                        )
+                    })
+            }),
+            ("POST", ["trees", certificate, "users", user_id, "unblock"]) => parse::<Unblock>(body).map(|request| {
+                database.unblock_user(user_id, &request.unblocker, request.reason, request.text.as_deref(), certificate, request.authorization).map(|()| {
+                    Response::new(
+                        200,
+                        json!({ "certificate": certificate, "user_id": user_id, "unblocker": request.unblocker }),
+                    )
+                })
+            }),
+            ("POST", ["trees", certificate, "users", user_id, "remove"]) => parse::<Remove>(body).map(|request| {
+                let orphans = request.orphans.clone();
+
+                database
+                    .remove_user(user_id, &request.remover, request.orphans, certificate, request.authorization, request.approvals)
+                    .map(|()| {
+                        Response::new(
+                            200,
+                            json!({ "certificate": certificate, "user_id": user_id, "remover": request.remover, "orphans": orphans }),
+                        )
+                    })
+            }),
+            ("POST", ["trees", certificate, "users", user_id, "move"]) => parse::<Move>(body).map(|request| {
+                database
+                    .move_user(user_id, &request.new_parent, &request.mover, certificate, request.authorization, request.approvals)
+                    .map(|()| {
+                        Response::new(
+                            200,
+                            json!({ "certificate": certificate, "user_id": user_id, "parent": request.new_parent, "mover": request.mover }),
+                        )
+                    })
+            }),
+            ("POST", ["trees", certificate, "users", user_id, "appeal"]) => parse::<FileAppeal>(body).map(|request| {
+                database
+                    .file_appeal(user_id, &request.statement, certificate, request.authorization)
+                    .map(|appeal| Response::new(201, json!({ "certificate": certificate, "appeal": appeal })))
+            }),
+            ("GET", ["trees", certificate, "appeals"]) => Ok(database
+                .appeals(certificate)
+                .map(|appeals| Response::new(200, json!({ "certificate": certificate, "appeals": appeals })))),
+            ("GET", ["trees", certificate, "appeals", appeal]) => appeal_id(appeal).map(|appeal| {
+                database
+                    .appeal(appeal, certificate)
+                    .map(|appeal| Response::new(200, json!({ "certificate": certificate, "appeal": appeal })))
+            }),
+            ("POST", ["trees", certificate, "appeals", appeal, "review"]) => appeal_id(appeal).and_then(|appeal| {
+                parse::<ReviewAppeal>(body).map(|request| {
+                    database
+                        .review_appeal(appeal, &request.reviewer, request.accept, request.keep_parent, certificate, request.authorization)
+                        .map(|appeal| Response::new(200, json!({ "certificate": certificate, "appeal": appeal })))
+                })
+            }),
+            ("POST", ["trees", certificate, "policy"]) => parse::<SetPolicy>(body).map(|request| {
+                let policy = request.policy.clone();
+
+                database
+                    .set_policy(request.policy, certificate, request.authorization)
+                    .map(|()| Response::new(200, json!({ "certificate": certificate, "policy": policy })))
+            }),
+            ("POST", ["trees", certificate, "transactions"]) => parse::<Transact>(body).map(|request| {
+                let events = request.events.len();
+                let mut transaction = database.transaction(certificate);
+
+                request
+                    .events
+                    .into_iter()
+                    .try_for_each(|signed| transaction.push_signed(signed))
+                    .and_then(|()| transaction.commit())
+                    .map(|()| Response::new(200, json!({ "certificate": certificate, "events": events })))
+            }),
+            ("POST", ["invitations", "redeem"]) => parse::<Redeem>(body).map(|request| {
+                let certificate = request.token.invitation.certificate.clone();
+                let parent = request.token.invitation.parent.clone();
+
+                database
+                    .redeem_invitation(request.token, &request.user_id, request.public_key, request.authorization)
+                    .map(|()| {
+                        Response::new(
+                            201,
+                            json!({ "certificate": certificate, "user_id": request.user_id, "parent": parent }),
+                        )
+                    })
+            }),
+            (_, ["trees", ..]) | (_, ["invitations", ..]) => return Response::error(405, "method not allowed"),
+            _ => return Response::error(404, "no such endpoint"),
+        };
+
+        match result {
+            Ok(Ok(response)) => response,
+            Ok(Err(error)) => Response::from_error(&error),
+            Err(response) => response,
+        }
+    }
+}
+
+/// Parses the id of an appeal in a path.
+fn appeal_id(segment: &str) -> Result<u64, Response> {
+    segment
+        .parse()
+        .map_err(|_| Response::error(400, &format!("invalid appeal id '{segment}'")))
+}
+
+/// Parses a JSON request body.
+fn parse<'a, T: Deserialize<'a>>(body: &'a [u8]) -> Result<T, Response> {
+    serde_json::from_slice(body).map_err(|error| Response::error(400, &format!("invalid request body: {error}")))
+}
+
+/// Reads the method, path and body of a request, or the response for a
+/// malformed one.
+fn read_request(reader: &mut impl BufRead) -> io::Result<Result<Request, Response>> {
+    let mut line = String::new();
+
+    if read_line(reader, &mut line)?.is_none() {
+        return Ok(Err(Response::error(400, "request line too long")));
+    }
+
+    let mut parts = line.split_whitespace();
+    let (method, path) = match (parts.next(), parts.next()) {
+        (Some(method), Some(path)) => (method.to_owned(), path.to_owned()),
+        _ => return Ok(Err(Response::error(400, "malformed request line"))),
+    };
+
+    let mut content_length = 0;
+
+    loop {
+        let mut header = String::new();
+
+        match read_line(reader, &mut header)? {
+            Some(0) => break,
+            Some(_) => {}
+            None => return Ok(Err(Response::error(431, "header line too long"))),
+        }
+
+        let header = header.trim_end();
+
+        if header.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = match value.trim().parse() {
+                    Ok(length) => length,
+                    Err(_) => return Ok(Err(Response::error(400, "invalid content-length"))),
+                };
+            }
+        }
+    }
+
+    if content_length > MAX_BODY {
+        return Ok(Err(Response::error(413, "request body too large")));
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    Ok(Ok(Request { method, path, body }))
+}
+
+/// Reads a line of at most `MAX_LINE` bytes, returning its length, or none
+/// for a longer line.
+fn read_line(reader: &mut impl BufRead, line: &mut String) -> io::Result<Option<usize>> {
+    let read = reader.take(MAX_LINE as u64).read_line(line)?;
+
+    if read == MAX_LINE && !line.ends_with('\n') {
+        return Ok(None);
+    }
+
+    Ok(Some(read))
+}
+
+fn write_response(mut stream: impl Write, response: &Response) -> io::Result<()> {
+    let body = response.body.to_string();
+    let reason = match response.status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        _ => "Internal Server Error",
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        reason,
+        body.len(),
+        body
+    )?;
+
+    stream.flush()
+}
+
+/// Decodes `%XX` escapes in a path segment.
+fn percent_decode(segment: &str) -> Option<String> {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+    use crate::event::MemoryEventLog;
+    use crate::storage::MemoryStorage;
+    use crate::testing::Fixture;
+    use crate::user::ADMIN_ID;
+
+    fn server() -> Server<MemoryStorage, MemoryEventLog> {
+        Server::new(Fixture::with_users(&[("alice", ADMIN_ID)]).database)
+    }
+
+    #[test]
+    fn requests_are_routed_to_their_operations() {
+        let server = server();
+
+        let permission = server.handle("GET", "/trees/post/users/alice/permission", b"");
+        assert_eq!(permission.status, 200);
+        assert_eq!(permission.body["permission"], true);
+
+        assert_eq!(server.handle("GET", "/trees/post/users/bob", b"").status, 404);
+        assert_eq!(server.handle("GET", "/trees/post/users/%zz", b"").status, 400);
+
+        // Signed by no one
+        let forged = json!({ "reporter": "alice", "reason": "spam", "nonce": 0, "signature": "00".repeat(64) });
+        let report = server.handle("POST", "/trees/post/users/admin/report", forged.to_string().as_bytes());
+        assert_eq!(report.status, 403, "{report:?}");
+    }
+
+    #[test]
+    fn idle_connections_do_not_hold_up_others() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || server().serve(listener));
+
+        let idle: Vec<TcpStream> = (0..WORKERS / 2).map(|_| TcpStream::connect(address).unwrap()).collect();
+
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.set_read_timeout(Some(TIMEOUT / 2)).unwrap();
+        stream
+            .write_all(b"GET /trees/post/root HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        drop(idle);
+    }
+
+    fn status(request: &[u8]) -> Option<u16> {
+        match read_request(&mut &request[..]).unwrap() {
+            Ok(_) => None,
+            Err(response) => Some(response.status),
+        }
+    }
+
+    #[test]
+    fn overlong_lines_are_refused() {
+        let path = "a".repeat(MAX_LINE);
+        let header = format!("X-Padding: {path}");
+
+        assert_eq!(status(format!("GET /{path} HTTP/1.1\r\n\r\n").as_bytes()), Some(400));
+        assert_eq!(
+            status(format!("GET / HTTP/1.1\r\n{header}\r\n\r\n").as_bytes()),
+            Some(431)
+        );
+
+        // A line just short of the cap is read whole
+        let header = &header[..MAX_LINE - 2];
+        assert_eq!(status(format!("GET / HTTP/1.1\r\n{header}\r\n\r\n").as_bytes()), None);
+    }
+
+    #[test]
+    fn reads_fail_once_the_deadline_passed() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        client.write_all(b"GET / HTTP/1.1\r\n").unwrap();
+
+        let mut connection = Deadline {
+            stream,
+            deadline: Instant::now(),
+        };
+        let error = connection.read(&mut [0; 16]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+    }
+}
@@ -0,0 +1,62 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use serde::Serialize;
+
+/// Counters of user records added, read and updated.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Statistics {
+    pub user_add: u32,
+    pub user_update: u32,
+    pub user_read: u32,
+}
+
+impl std::ops::AddAssign for Statistics {
+    fn add_assign(&mut self, other: Statistics) {
+        self.user_add += other.user_add;
+        self.user_update += other.user_update;
+        self.user_read += other.user_read;
+    }
+}
+
+/// Statistics of a user tree, counted through shared references so that
+/// concurrent readers of a tree can count their reads.
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    user_add: AtomicU32,
+    user_update: AtomicU32,
+    user_read: AtomicU32,
+}
+
+impl Counters {
+    pub(crate) fn add(&self, count: u32) {
+        self.user_add.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub(crate) fn update(&self, count: u32) {
+        self.user_update.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub(crate) fn read(&self, count: u32) {
+        self.user_read.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> Statistics {
+        Statistics {
+            user_add: self.user_add.load(Ordering::Relaxed),
+            user_update: self.user_update.load(Ordering::Relaxed),
+            user_read: self.user_read.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Clone for Counters {
+    fn clone(&self) -> Counters {
+        let statistics = self.snapshot();
+
+        Counters {
+            user_add: AtomicU32::new(statistics.user_add),
+            user_update: AtomicU32::new(statistics.user_update),
+            user_read: AtomicU32::new(statistics.user_read),
+        }
+    }
+}
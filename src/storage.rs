@@ -0,0 +1,387 @@
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Result};
+use crate::fsck::{StoredTree, TreeReport, Violation};
+use crate::tree::UserTree;
+
+/// Backend persisting the user trees of a database.
+///
+/// The database keeps every tree in memory behind its own lock. It takes the
+/// stored trees once when it is created, and `save` is called after every
+/// successful mutation of a tree so that durable backends can persist it.
+pub trait Storage {
+    /// Hands over the trees stored when the backend was opened.
+    fn load(&mut self) -> Vec<UserTree>;
+
+    /// Persists the current state of a user tree, new or existing.
+    fn save(&mut self, tree: &UserTree) -> Result<()>;
+}
+
+/// Process-local storage, lost when the process exits.
+///
+/// Keeps nothing beyond the trees the database already holds in memory.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryStorage;
+
+impl MemoryStorage {
+    pub fn new() -> MemoryStorage {
+        MemoryStorage
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn load(&mut self) -> Vec<UserTree> {
+        Vec::new()
+    }
+
+    fn save(&mut self, _tree: &UserTree) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Storage keeping each user tree in its own JSON file of a directory.
+///
+/// A save serializes and rewrites only the file of the tree that changed,
+/// atomically: through a synced temporary file, a rename and a sync of the
+/// directory. A database file of earlier versions, holding every tree, is
+/// converted to a directory on open and kept with a `.legacy` suffix.
+#[derive(Debug)]
+pub struct FileStorage {
+    path: PathBuf,
+    /// Certificates with a file in the directory.
+    certificates: BTreeSet<String>,
+    loaded: Vec<UserTree>,
+}
+
+impl FileStorage {
+    /// Opens the database directory at path, starting empty if it does not
+    /// exist. The directory is created by the first save.
+    pub fn open(path: impl AsRef<Path>) -> Result<FileStorage> {
+        let path = path.as_ref().to_path_buf();
+        let staging = with_suffix(&path, ".migrating");
+
+        if path.is_file() {
+            migrate(&path, &staging)?;
+        } else if !path.exists() && staging.is_dir() && with_suffix(&path, ".legacy").is_file() {
+            // A conversion stopped after the old file was set aside
+            fs::rename(&staging, &path).map_err(|error| storage_error(&path, error))?;
+            sync_parent(&path)?;
+        }
+
+        let mut loaded = Vec::new();
+
+        for file in tree_files(&path)? {
+            let bytes = fs::read(&file).map_err(|error| storage_error(&file, error))?;
+            loaded.push(serde_json::from_slice::<UserTree>(&bytes).map_err(|error| storage_error(&file, error))?);
+        }
+
+        Ok(FileStorage {
+            certificates: loaded.iter().map(|tree| tree.certificate().to_owned()).collect(),
+            path,
+            loaded,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Checks if the directory holds no user tree.
+    pub fn is_empty(&self) -> bool {
+        self.certificates.is_empty()
+    }
+
+    /// Checks every tree in the database at path without loading it, so that
+    /// databases the database refuses to open can be checked too. With
+    /// repair, the repairable violations are fixed and the files of the
+    /// repaired trees rewritten.
+    pub fn fsck(path: impl AsRef<Path>, repair: bool) -> Result<Vec<TreeReport>> {
+        let path = path.as_ref();
+
+        // Database files of earlier versions are checked in place
+        let legacy = path.is_file();
+
+        let (mut trees, files): (Vec<StoredTree>, Vec<PathBuf>) = if legacy {
+            (read_json(path)?, Vec::new())
+        } else {
+            let files = tree_files(path)?;
+            (files.iter().map(|file| read_json(file)).collect::<Result<_>>()?, files)
+        };
+
+        let mut reports = Vec::with_capacity(trees.len());
+
+        for (index, tree) in trees.iter_mut().enumerate() {
+            let violations = if repair { tree.repair() } else { tree.check() };
+            let repaired = repair && violations.iter().any(Violation::is_repairable);
+
+            if repaired && !legacy {
+                write_atomically(&files[index], to_json(&files[index], &tree)?)?;
+            }
+
+            reports.push(TreeReport {
+                certificate: tree.certificate.clone(),
+                violations,
+                repaired,
+            });
+        }
+
+        if legacy && reports.iter().any(|report| report.repaired) {
+            write_atomically(path, to_json(path, &trees)?)?;
+        }
+
+        Ok(reports)
+    }
+
+    fn tree_path(&self, certificate: &str) -> PathBuf {
+        self.path.join(file_name(certificate))
+    }
+}
+
+impl Storage for FileStorage {
+    fn load(&mut self) -> Vec<UserTree> {
+        std::mem::take(&mut self.loaded)
+    }
+
+    fn save(&mut self, tree: &UserTree) -> Result<()> {
+        if !self.path.is_dir() {
+            fs::create_dir_all(&self.path).map_err(|error| storage_error(&self.path, error))?;
+            sync_parent(&self.path)?;
+        }
+
+        let file = self.tree_path(tree.certificate());
+        write_atomically(&file, to_json(&file, tree)?)?;
+        self.certificates.insert(tree.certificate().to_owned());
+
+        Ok(())
+    }
+}
+
+/// Converts a database file of earlier versions into a directory: the trees
+/// are written to a staging directory, the file is set aside and the staging
+/// directory renamed in its place.
+fn migrate(path: &Path, staging: &Path) -> Result<()> {
+    let trees: Vec<serde_json::Value> = read_json(path)?;
+
+    if staging.exists() {
+        fs::remove_dir_all(staging).map_err(|error| storage_error(staging, error))?;
+    }
+
+    fs::create_dir(staging).map_err(|error| storage_error(staging, error))?;
+
+    for tree in trees.iter() {
+        let certificate = tree["certificate"].as_str().unwrap_or_default();
+        let file = staging.join(file_name(certificate));
+        write_atomically(&file, to_json(&file, tree)?)?;
+    }
+
+    let legacy = with_suffix(path, ".legacy");
+    fs::rename(path, &legacy).map_err(|error| storage_error(path, error))?;
+    fs::rename(staging, path).map_err(|error| storage_error(path, error))?;
+    sync_parent(path)
+}
+
+/// The tree files of a database directory, sorted, none if it does not
+/// exist.
+fn tree_files(path: &Path) -> Result<Vec<PathBuf>> {
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(storage_error(path, error)),
+    };
+
+    let mut files = Vec::new();
+
+    for entry in entries {
+        let file = entry.map_err(|error| storage_error(path, error))?.path();
+
+        // Temporary files of an interrupted save are not trees
+        if file.extension().is_some_and(|extension| extension == "json") {
+            files.push(file);
+        }
+    }
+
+    files.sort();
+
+    Ok(files)
+}
+
+/// Name of a certificate's file, with every character but ASCII letters,
+/// digits, `-` and `_` percent-encoded.
+fn file_name(certificate: &str) -> String {
+    let mut name = String::with_capacity(certificate.len() + 5);
+
+    for byte in certificate.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' {
+            name.push(char::from(byte));
+        } else {
+            name.push_str(&format!("%{byte:02X}"));
+        }
+    }
+
+    name + ".json"
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T> {
+    let bytes = fs::read(path).map_err(|error| storage_error(path, error))?;
+    serde_json::from_slice(&bytes).map_err(|error| storage_error(path, error))
+}
+
+fn to_json(path: &Path, value: &impl serde::Serialize) -> Result<Vec<u8>> {
+    serde_json::to_vec(value).map_err(|error| storage_error(path, error))
+}
+
+/// Writes the file through a temporary file and a rename, syncing the
+/// temporary file before the rename and the directory after it, so that a
+/// crash leaves either the old or the new contents.
+fn write_atomically(path: &Path, contents: Vec<u8>) -> Result<()> {
+    let temporary = with_suffix(path, ".tmp");
+
+    let mut file = File::create(&temporary).map_err(|error| storage_error(path, error))?;
+    file.write_all(&contents).map_err(|error| storage_error(path, error))?;
+    file.sync_all().map_err(|error| storage_error(path, error))?;
+    drop(file);
+
+    fs::rename(&temporary, path).map_err(|error| storage_error(path, error))?;
+    sync_parent(path)
+}
+
+/// Syncs the directory holding path, so that a rename or creation in it is
+/// durable.
+fn sync_parent(path: &Path) -> Result<()> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    // Directories can only be opened and synced on Unix
+    if cfg!(unix) {
+        File::open(parent)
+            .and_then(|directory| directory.sync_all())
+            .map_err(|error| storage_error(parent, error))?;
+    }
+
+    Ok(())
+}
+
+fn storage_error(path: &Path, error: impl std::fmt::Display) -> Error {
+    Error::Storage {
+        path: path.display().to_string(),
+        message: error.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Database;
+    use crate::event::{EventLog, MemoryEventLog};
+    use crate::testing::{Fixture, CERTIFICATE};
+    use crate::user::ADMIN_ID;
+
+    /// An empty scratch path of the test, removed when dropped.
+    struct Scratch(PathBuf);
+
+    impl Scratch {
+        fn new(name: &str) -> Scratch {
+            let path = std::env::temp_dir().join(format!("tree-org-{name}-{}", std::process::id()));
+            Scratch::remove(&path);
+            Scratch(path)
+        }
+
+        fn remove(path: &Path) {
+            for path in [path.to_path_buf(), with_suffix(path, ".legacy")] {
+                let _ = fs::remove_dir_all(&path).or_else(|_| fs::remove_file(&path));
+            }
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            Scratch::remove(&self.0);
+        }
+    }
+
+    fn fixture() -> Fixture {
+        let fixture = Fixture::with_users(&[("alice", ADMIN_ID), ("bob", "alice")]);
+        fixture.block("bob", "alice", None).unwrap();
+        fixture
+    }
+
+    fn reopened(path: &Path) -> Database<FileStorage> {
+        Database::with_storage(FileStorage::open(path).unwrap())
+    }
+
+    #[test]
+    fn trees_reopen_from_their_own_files() {
+        let scratch = Scratch::new("storage");
+        let fixture = fixture();
+        let log = fixture.database.log().clone();
+        let root = fixture.database.tree_root(CERTIFICATE).unwrap();
+
+        Database::replay(FileStorage::open(&scratch.0).unwrap(), log).unwrap();
+        assert_eq!(tree_files(&scratch.0).unwrap(), [scratch.0.join("post.json")]);
+
+        let database = reopened(&scratch.0);
+        assert_eq!(database.tree_root(CERTIFICATE).unwrap(), root);
+        assert!(!database.check_user_permission("bob", CERTIFICATE).unwrap().is_granted());
+        assert_eq!(file_name("a/b c"), "a%2Fb%20c.json");
+    }
+
+    #[test]
+    fn replays_catch_trees_up_with_the_events_their_saves_missed() {
+        let scratch = Scratch::new("behind");
+        let fixture = fixture();
+        let log = fixture.database.log().clone();
+        let events = log.events().unwrap();
+
+        // The save of the last event failed after its append
+        let mut behind = MemoryEventLog::new();
+        behind.append_all(&events[..events.len() - 1]).unwrap();
+        Database::replay(FileStorage::open(&scratch.0).unwrap(), behind).unwrap();
+        assert!(reopened(&scratch.0)
+            .check_user_permission("bob", CERTIFICATE)
+            .unwrap()
+            .is_granted());
+
+        let database = Database::replay(FileStorage::open(&scratch.0).unwrap(), log).unwrap();
+        assert_eq!(
+            database.tree_root(CERTIFICATE).unwrap(),
+            fixture.database.tree_root(CERTIFICATE).unwrap()
+        );
+
+        let database = reopened(&scratch.0);
+        assert!(!database.check_user_permission("bob", CERTIFICATE).unwrap().is_granted());
+        assert_eq!(
+            database.read_tree(CERTIFICATE, UserTree::logged).unwrap(),
+            events.len() as u64
+        );
+    }
+
+    #[test]
+    fn database_files_of_earlier_versions_become_directories() {
+        let scratch = Scratch::new("legacy");
+        let fixture = fixture();
+        let trees = fixture
+            .database
+            .read_tree(CERTIFICATE, |tree| serde_json::to_vec(&[tree]).unwrap())
+            .unwrap();
+        fs::write(&scratch.0, trees).unwrap();
+
+        let database = reopened(&scratch.0);
+        assert!(scratch.0.is_dir());
+        assert!(with_suffix(&scratch.0, ".legacy").is_file());
+        assert_eq!(
+            database.tree_root(CERTIFICATE).unwrap(),
+            fixture.database.tree_root(CERTIFICATE).unwrap()
+        );
+    }
+}
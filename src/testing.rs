@@ -0,0 +1,130 @@
+//! A small certificate tree the tests build on.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::clock::ManualClock;
+use crate::database::Database;
+use crate::delegation::Limits;
+use crate::error::Result;
+use crate::event::{Event, SignedEvent};
+use crate::history::BlockReason;
+use crate::identity::SecretKey;
+use crate::role::Role;
+use crate::user::ADMIN_ID;
+
+pub(crate) const CERTIFICATE: &str = "post";
+
+/// A database holding one tree, with a key per user and a clock the test
+/// moves.
+pub(crate) struct Fixture {
+    pub database: Database,
+    pub clock: Arc<ManualClock>,
+    keys: HashMap<String, SecretKey>,
+}
+
+impl Fixture {
+    /// A tree holding the admin only.
+    pub fn new() -> Fixture {
+        let clock = Arc::new(ManualClock::new(1_000_000));
+        let database = Database::new().with_clock(Arc::clone(&clock));
+        let key = SecretKey::generate();
+
+        let event = Event::AddUserTree {
+            certificate: CERTIFICATE.to_owned(),
+            admin_key: key.public_key(),
+        };
+        let authorization = database.authorize(&event, &key);
+        database
+            .add_user_tree(CERTIFICATE, key.public_key(), authorization)
+            .unwrap();
+
+        Fixture {
+            database,
+            clock,
+            keys: HashMap::from([(ADMIN_ID.to_owned(), key)]),
+        }
+    }
+
+    /// A tree holding the users, each a moderator under its parent, in order.
+    pub fn with_users(users: &[(&str, &str)]) -> Fixture {
+        let mut fixture = Fixture::new();
+
+        for (user_id, parent) in users {
+            fixture.add(user_id, parent, Role::Moderator).unwrap();
+        }
+
+        fixture
+    }
+
+    pub fn key(&self, user_id: &str) -> &SecretKey {
+        &self.keys[user_id]
+    }
+
+    /// Adds a user with a new key under the parent, signed by the parent.
+    pub fn add(&mut self, user_id: &str, parent: &str, role: Role) -> Result<()> {
+        self.add_limited(user_id, parent, role, Limits::NONE)
+    }
+
+    /// Adds a user whose subtree the parent limits.
+    pub fn add_limited(&mut self, user_id: &str, parent: &str, role: Role, limits: Limits) -> Result<()> {
+        let key = self
+            .keys
+            .entry(user_id.to_owned())
+            .or_insert_with(SecretKey::generate)
+            .clone();
+        let event = Event::AddUser {
+            certificate: CERTIFICATE.to_owned(),
+            user_id: user_id.to_owned(),
+            parent: parent.to_owned(),
+            role,
+            limits,
+            public_key: key.public_key(),
+        };
+
+        self.submit(event)
+    }
+
+    /// Signs an event with its actor's key for the actor's current nonce and
+    /// submits it.
+    pub fn submit(&self, event: Event) -> Result<()> {
+        self.submit_approved(event, &[])
+    }
+
+    /// Submits an event signed by its actor and approved by the approvers.
+    pub fn submit_approved(&self, event: Event, approvers: &[&str]) -> Result<()> {
+        let actor = event.actor().expect("signed events have an actor");
+        let authorization = self.database.authorize(&event, self.key(actor));
+        let approvals = approvers
+            .iter()
+            .map(|approver| self.database.approve(&event, approver, self.key(approver)))
+            .collect();
+
+        self.database.submit(SignedEvent {
+            event,
+            authorization: Some(authorization),
+            approvals,
+            applied_at: None,
+        })
+    }
+
+    /// Blocks a user for good, or for a number of seconds.
+    pub fn block(&self, user_id: &str, blocker: &str, duration: Option<u64>) -> Result<()> {
+        self.submit(Event::BlockUser {
+            certificate: CERTIFICATE.to_owned(),
+            user_id: user_id.to_owned(),
+            blocker: blocker.to_owned(),
+            duration,
+            reason: BlockReason::Other,
+            text: None,
+        })
+    }
+
+    /// Whether a user has permission now.
+    pub fn permitted(&self, user_id: &str) -> bool {
+        self.database
+            .check_user_permission(user_id, CERTIFICATE)
+            .unwrap()
+            .is_granted()
+    }
+}
@@ -0,0 +1,247 @@
+use crate::database::Database;
+use crate::error::{Error, Result};
+use crate::event::{Authorization, Event, EventLog, SignedEvent};
+use crate::identity::SecretKey;
+use crate::storage::Storage;
+
+/// Tree operations on one certificate, applied together: all of them or none.
+///
+/// Operations are signed as if every earlier operation of the transaction
+/// had already been applied, so an actor's operations use consecutive nonces
+/// and users added by the transaction can act in it.
+pub struct Transaction<'a, S: Storage, L: EventLog> {
+    database: &'a Database<S, L>,
+    certificate: String,
+    events: Vec<SignedEvent>,
+}
+
+impl<'a, S: Storage, L: EventLog> Transaction<'a, S, L> {
+    pub(crate) fn new(database: &'a Database<S, L>, certificate: &str) -> Transaction<'a, S, L> {
+        Transaction {
+            database,
+            certificate: certificate.to_owned(),
+            events: Vec::new(),
+        }
+    }
+
+    pub fn certificate(&self) -> &str {
+        &self.certificate
+    }
+
+    pub fn events(&self) -> &[SignedEvent] {
+        &self.events
+    }
+
+    /// Nonce the user's next operation in the transaction must be signed for.
+    pub fn nonce(&self, user_id: &str) -> u64 {
+        let pending = self
+            .events
+            .iter()
+            .filter(|signed| signed.event.actor() == Some(user_id))
+            .count() as u64;
+        self.database.nonce(user_id, &self.certificate).unwrap_or(0) + pending
+    }
+
+    /// Signs an event with the actor's key for the actor's nonce within the
+    /// transaction.
+    pub fn authorize(&self, event: &Event, key: &SecretKey) -> Authorization {
+        let nonce = match (event, event.actor()) {
+            (Event::AddUserTree { .. } | Event::RedeemInvitation { .. }, _) | (_, None) => 0,
+            (_, Some(actor)) => self.nonce(actor),
+        };
+
+        Authorization::sign(event, nonce, key)
+    }
+
+    /// Adds an operation to the transaction. It is only checked once the
+    /// transaction is committed.
+    pub fn push(&mut self, event: Event, authorization: Option<Authorization>) -> Result<()> {
+        self.push_signed(SignedEvent {
+            event,
+            authorization,
+            approvals: Vec::new(),
+            applied_at: None,
+        })
+    }
+
+    /// Adds an operation signed elsewhere, possibly with approvals.
+    pub fn push_signed(&mut self, signed: SignedEvent) -> Result<()> {
+        if signed.event.certificate() != self.certificate {
+            return Err(Error::CertificateMismatch {
+                expected: self.certificate.clone(),
+                found: signed.event.certificate().to_owned(),
+            });
+        }
+
+        self.events.push(signed);
+
+        Ok(())
+    }
+
+    /// Applies every operation in order, or none of them if one fails.
+    pub fn commit(self) -> Result<()> {
+        self.database.submit_transaction(self.events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::delegation::Limits;
+    use crate::event::Event;
+    use crate::history::BlockReason;
+    use crate::identity::SecretKey;
+    use crate::policy::Policy;
+    use crate::report::ReportReason;
+    use crate::role::Role;
+    use crate::testing::{Fixture, CERTIFICATE};
+    use crate::tree::Orphans;
+    use crate::user::ADMIN_ID;
+
+    fn add(user_id: &str, parent: &str, key: &SecretKey) -> Event {
+        Event::AddUser {
+            certificate: CERTIFICATE.to_owned(),
+            user_id: user_id.to_owned(),
+            parent: parent.to_owned(),
+            role: Role::Inviter,
+            limits: Limits::NONE,
+            public_key: key.public_key(),
+        }
+    }
+
+    #[test]
+    fn users_added_in_a_transaction_can_act_in_it() {
+        let fixture = Fixture::new();
+        let nonce = fixture.database.nonce(ADMIN_ID, CERTIFICATE).unwrap();
+        let (alice, bob) = (SecretKey::generate(), SecretKey::generate());
+        let mut transaction = fixture.database.transaction(CERTIFICATE);
+
+        for (event, key) in [
+            (add("alice", ADMIN_ID, &alice), fixture.key(ADMIN_ID)),
+            (add("bob", "alice", &bob), &alice),
+        ] {
+            let authorization = transaction.authorize(&event, key);
+            transaction.push(event, Some(authorization)).unwrap();
+        }
+
+        transaction.commit().unwrap();
+        assert!(fixture.permitted("bob"));
+        assert_eq!(fixture.database.nonce(ADMIN_ID, CERTIFICATE).unwrap(), nonce + 1);
+    }
+
+    #[test]
+    fn failing_transactions_change_nothing() {
+        let fixture = Fixture::with_users(&[("alice", ADMIN_ID)]);
+        let root = fixture.database.tree_root(CERTIFICATE).unwrap();
+        let carol = SecretKey::generate();
+        let mut transaction = fixture.database.transaction(CERTIFICATE);
+
+        // The second operation adds a user the first one already added
+        for _ in 0..2 {
+            let event = add("carol", "alice", &carol);
+            let authorization = transaction.authorize(&event, fixture.key("alice"));
+            transaction.push(event, Some(authorization)).unwrap();
+        }
+
+        assert!(transaction.commit().is_err());
+        assert!(fixture.database.user_info("carol", CERTIFICATE).is_err());
+        assert_eq!(fixture.database.nonce("alice", CERTIFICATE).unwrap(), 0);
+        assert_eq!(fixture.database.tree_root(CERTIFICATE).unwrap(), root);
+    }
+
+    #[test]
+    fn failing_transactions_undo_every_kind_of_change() {
+        let fixture = Fixture::with_users(&[
+            ("alice", ADMIN_ID),
+            ("bob", "alice"),
+            ("carol", "bob"),
+            ("dave", ADMIN_ID),
+            ("erin", "dave"),
+        ]);
+        let event = fixture
+            .database
+            .remove_event("erin", "erin", Orphans::Cascade, CERTIFICATE);
+        fixture.submit(event).unwrap();
+
+        let frank = SecretKey::generate();
+        let events = [
+            (add("frank", "alice", &frank), "alice"),
+            (
+                Event::ReportUser {
+                    certificate: CERTIFICATE.to_owned(),
+                    user_id: "carol".to_owned(),
+                    reporter: "dave".to_owned(),
+                    reason: ReportReason::Spam,
+                    text: None,
+                },
+                "dave",
+            ),
+            (
+                Event::BlockUser {
+                    certificate: CERTIFICATE.to_owned(),
+                    user_id: "carol".to_owned(),
+                    blocker: "bob".to_owned(),
+                    duration: None,
+                    reason: BlockReason::default(),
+                    text: None,
+                },
+                "bob",
+            ),
+            (
+                Event::SetPolicy {
+                    certificate: CERTIFICATE.to_owned(),
+                    policy: Policy::default(),
+                },
+                ADMIN_ID,
+            ),
+            (
+                fixture
+                    .database
+                    .remove_event("bob", "alice", Orphans::ReattachToParent, CERTIFICATE),
+                "alice",
+            ),
+            (
+                Event::FileAppeal {
+                    certificate: CERTIFICATE.to_owned(),
+                    user_id: "carol".to_owned(),
+                    statement: "not spam".to_owned(),
+                },
+                "carol",
+            ),
+            (
+                fixture
+                    .database
+                    .remove_event("dave", ADMIN_ID, Orphans::Cascade, CERTIFICATE),
+                ADMIN_ID,
+            ),
+            // Fails, as erin was removed
+            (
+                fixture
+                    .database
+                    .remove_event("erin", ADMIN_ID, Orphans::Cascade, CERTIFICATE),
+                ADMIN_ID,
+            ),
+        ];
+        let mut transaction = fixture.database.transaction(CERTIFICATE);
+
+        for (event, actor) in events {
+            let key = fixture.key(actor);
+            let authorization = transaction.authorize(&event, key);
+            transaction.push(event, Some(authorization)).unwrap();
+        }
+
+        let snapshot = || {
+            let tree = fixture
+                .database
+                .read_tree(CERTIFICATE, |tree| serde_json::to_value(tree).unwrap())
+                .unwrap();
+            (tree, fixture.database.tree_root(CERTIFICATE).unwrap())
+        };
+        let before = snapshot();
+        let statistics = fixture.database.statistics();
+
+        assert!(transaction.commit().is_err());
+        assert_eq!(fixture.database.statistics(), statistics);
+        assert_eq!(snapshot(), before);
+        assert!(fixture.database.user_info("frank", CERTIFICATE).is_err());
+    }
+}
@@ -0,0 +1,2291 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::appeal::{Appeal, AppealState, Vote};
+use crate::delegation::{Limits, Permission};
+use crate::error::{Error, Result};
+use crate::event::{Approval, Authorization, Event, SignedEvent};
+use crate::fsck::StoredTree;
+use crate::history::{BlockAction, BlockReason, BlockRecord};
+use crate::identity::PublicKey;
+use crate::intern::{Handle, Interner};
+use crate::invitation::SignedInvitation;
+use crate::merkle::{CommittedUser, Digest, InclusionProof, MerkleTree};
+use crate::moderation::{AuditEntry, AutomaticAction, Rule};
+use crate::policy::Policy;
+use crate::report::{Report, ReportReason, ReportScore};
+use crate::role::{Capabilities, Capability, Role};
+use crate::statistics::{Counters, Statistics};
+use crate::user::{User, ADMIN_ID};
+
+/// Users of a single certificate, rooted at the admin.
+///
+/// Users live in an arena indexed by the handles of their interned ids. A
+/// user's handle is also the index of its leaf in the commitment.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "StoredTree")]
+pub struct UserTree {
+    certificate: String,
+    ids: Interner,
+    nodes: Vec<Node>,
+    /// Nonces of the invitations redeemed so far.
+    redeemed_invitations: BTreeSet<String>,
+    /// Next nonces of removed users, so that their signatures can not be
+    /// replayed if they are added again.
+    removed: BTreeMap<String, u64>,
+    policy: Policy,
+    /// Actions the policy's rules took by themselves.
+    audit: Vec<AuditEntry>,
+    /// Appeals against blocks, indexed by their ids.
+    appeals: Vec<Appeal>,
+    commitment: MerkleTree,
+    /// Length of the event log once the tree's last event was appended, so
+    /// that the log's later events for the tree can be replayed into it.
+    logged: u64,
+    /// Unix time limits are evaluated at, zero unless set.
+    time: u64,
+    statistics: Counters,
+    /// The atomic change in progress, if any.
+    journal: Option<Journal>,
+}
+
+/// What happens to the children of a removed user.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "strategy", rename_all = "kebab-case")]
+pub enum Orphans {
+    /// Remove the user's whole subtree with it.
+    Cascade,
+    /// Move the children under the removed user's parent.
+    ReattachToParent,
+    /// Move the children under the adopter.
+    Adopt { adopter: String },
+}
+
+impl fmt::Display for Orphans {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Orphans::Cascade => f.write_str("cascade"),
+            Orphans::ReattachToParent => f.write_str("reattach to parent"),
+            Orphans::Adopt { adopter } => write!(f, "adopt by {adopter}"),
+        }
+    }
+}
+
+/// A user in the arena, referring to other users by handle.
+#[derive(Debug, Clone)]
+struct Node {
+    /// The node itself for the admin.
+    parent: Handle,
+    children: Vec<Handle>,
+    /// Position of the node among its parent's children.
+    slot: usize,
+    reports: u16,
+    report_log: Vec<Report>,
+    blocked: bool,
+    /// Unix time a temporary block ends at.
+    blocked_until: Option<u64>,
+    block_history: Vec<BlockRecord>,
+    role: Role,
+    /// Capabilities the rules suspended, of the node alone.
+    suspended: Capabilities,
+    /// The rule that suspended the node's invite capability.
+    suspended_by: Option<Rule>,
+    limits: Limits,
+    public_key: PublicKey,
+    nonce: u64,
+    grant: Option<SignedEvent>,
+    ancestry: Ancestry,
+}
+
+/// Permission state a user inherits from itself and its ancestors, kept up
+/// to date so that permission checks do not walk the tree.
+#[derive(Debug, Clone, Copy)]
+struct Ancestry {
+    /// Number of users blocked for good among the user and its ancestors.
+    blocked: u32,
+    /// Latest expiry of a temporary block among the user and its ancestors,
+    /// zero if there is none.
+    blocked_until: u64,
+    capabilities: Capabilities,
+    limits: Limits,
+}
+
+impl Ancestry {
+    /// What the admin inherits.
+    fn root() -> Ancestry {
+        Ancestry {
+            blocked: 0,
+            blocked_until: 0,
+            capabilities: Capabilities::all(),
+            limits: Limits::NONE,
+        }
+    }
+
+    /// What a user not reachable from the admin inherits: no permission.
+    fn unreachable() -> Ancestry {
+        Ancestry {
+            blocked: 1,
+            blocked_until: 0,
+            capabilities: Capabilities::NONE,
+            limits: Limits::NONE,
+        }
+    }
+
+    /// What a node inherits, given what its parent inherits.
+    fn child(self, node: &Node) -> Ancestry {
+        let (blocked, blocked_until) = match (node.blocked, node.blocked_until) {
+            (true, None) => (self.blocked + 1, self.blocked_until),
+            (true, Some(until)) => (self.blocked, self.blocked_until.max(until)),
+            (false, _) => (self.blocked, self.blocked_until),
+        };
+
+        Ancestry {
+            blocked,
+            blocked_until,
+            capabilities: self.capabilities.intersection(node.role.capabilities()),
+            limits: self.limits.combine(node.limits),
+        }
+    }
+}
+
+impl Node {
+    /// Whether the node is blocked at the Unix time now, which it is not
+    /// once a temporary block ended.
+    fn is_blocked_at(&self, now: u64) -> bool {
+        self.blocked && self.blocked_until.is_none_or(|until| now < until)
+    }
+
+    fn new(parent: Handle, role: Role, limits: Limits, public_key: PublicKey) -> Node {
+        Node {
+            parent,
+            children: Vec::new(),
+            slot: 0,
+            reports: 0,
+            report_log: Vec::new(),
+            blocked: false,
+            blocked_until: None,
+            block_history: Vec::new(),
+            role,
+            suspended: Capabilities::NONE,
+            suspended_by: None,
+            limits,
+            public_key,
+            nonce: 0,
+            grant: None,
+            ancestry: Ancestry::unreachable(),
+        }
+    }
+}
+
+/// What a tree changed since it began an atomic change, so that a failed
+/// change restores only what it touched instead of working on a copy.
+#[derive(Debug, Clone)]
+struct Journal {
+    /// How to undo each change, oldest first.
+    undo: Vec<Undo>,
+    time: u64,
+    logged: u64,
+    statistics: Counters,
+}
+
+/// A change to a tree, holding what it replaced.
+#[derive(Debug, Clone)]
+enum Undo {
+    /// Any change to a node.
+    Node(Handle, Box<Node>),
+    Ancestry(Handle, Ancestry),
+    Reports(Handle, u16),
+    Nonce(Handle, u64),
+    Grant(Handle, Box<Option<SignedEvent>>),
+    Rights(Handle, Role, Limits),
+    /// A node's parent and its slot among the parent's children.
+    Placed(Handle, Handle, usize),
+    /// A child was pushed onto the parent's children.
+    Attached(Handle),
+    /// The child at a slot of the parent's children was swap removed.
+    Detached(Handle, usize, Handle),
+    /// The child at a slot of the parent's children was replaced.
+    Child(Handle, usize, Handle),
+    Leaf(usize, Digest),
+    /// A user was pushed onto the arena, the interner and the commitment.
+    Added,
+    /// A user was swap removed from the arena, the interner and the
+    /// commitment, with its node, id and leaf.
+    Removed(Handle, Box<Node>, String, Digest),
+    RemovedNonce(String, Option<u64>),
+    Redeemed(String),
+    Policy(Box<Policy>),
+    Audited,
+    Filed,
+    Appeal(usize, Box<Appeal>),
+}
+
+/// Rebuilds the arena and the commitment from the stored form, rejecting
+/// trees that violate an invariant.
+impl TryFrom<StoredTree> for UserTree {
+    type Error = String;
+
+    fn try_from(data: StoredTree) -> std::result::Result<UserTree, String> {
+        if let Some(violation) = data.check().first() {
+            return Err(format!("{violation}, see `tree-org fsck`"));
+        }
+
+        let mut users = data.users;
+
+        // Trees stored before the commitment existed get their leaves sorted by id
+        let mut leaves = data.leaves;
+
+        if leaves.len() != users.len() || leaves.iter().any(|user_id| !users.contains_key(user_id)) {
+            leaves = users.keys().cloned().collect();
+            leaves.sort();
+        }
+
+        let mut ids = Interner::default();
+
+        for user_id in leaves.iter() {
+            ids.intern(user_id);
+        }
+
+        let handle = |user_id: &str| ids.get(user_id).ok_or_else(|| format!("unknown user '{user_id}'"));
+        let mut nodes = Vec::with_capacity(leaves.len());
+
+        for user_id in leaves.iter() {
+            let user = users.remove(user_id).expect("leaves are the ids of the users");
+            let mut node = Node::new(handle(&user.parent)?, user.role, user.limits, user.public_key);
+
+            node.children = user
+                .children
+                .iter()
+                .map(|child| handle(child))
+                .collect::<std::result::Result<_, _>>()?;
+            node.reports = user.reports;
+            node.report_log = user.report_log;
+            node.suspended = user.suspended;
+            node.suspended_by = user.suspended_by;
+            node.blocked = user.blocked;
+            node.blocked_until = user.blocked_until;
+            node.block_history = user.block_history;
+            node.nonce = user.nonce;
+            node.grant = user.grant;
+
+            nodes.push(node);
+        }
+
+        for parent in ids.handles() {
+            for (slot, child) in nodes[parent.index()].children.clone().into_iter().enumerate() {
+                nodes[child.index()].slot = slot;
+            }
+        }
+
+        let admin = ids.get(ADMIN_ID).expect("checked trees have an admin");
+
+        // Trees stored before roles existed have no role for the admin
+        nodes[admin.index()].role = Role::CoAdmin;
+
+        let mut tree = UserTree {
+            certificate: data.certificate,
+            ids,
+            nodes,
+            redeemed_invitations: data.redeemed_invitations,
+            removed: data.removed,
+            policy: data.policy,
+            audit: data.audit,
+            appeals: data.appeals,
+            commitment: MerkleTree::default(),
+            logged: data.logged,
+            time: 0,
+            statistics: Counters::default(),
+            journal: None,
+        };
+
+        for handle in tree.ids.handles() {
+            tree.commitment.push(tree.leaf(handle));
+        }
+
+        tree.reindex(admin);
+
+        Ok(tree)
+    }
+}
+
+/// Stores users as a map from their ids, the way trees were stored before
+/// the arena, with the leaf order alongside.
+impl Serialize for UserTree {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut tree = serializer.serialize_struct("UserTree", 9)?;
+        tree.serialize_field("certificate", &self.certificate)?;
+        tree.serialize_field("users", &StoredUsers(self))?;
+        tree.serialize_field("redeemed_invitations", &self.redeemed_invitations)?;
+
+        if self.removed.is_empty() {
+            tree.skip_field("removed")?;
+        } else {
+            tree.serialize_field("removed", &self.removed)?;
+        }
+
+        if self.policy.is_default() {
+            tree.skip_field("policy")?;
+        } else {
+            tree.serialize_field("policy", &self.policy)?;
+        }
+
+        if self.audit.is_empty() {
+            tree.skip_field("audit")?;
+        } else {
+            tree.serialize_field("audit", &self.audit)?;
+        }
+
+        if self.appeals.is_empty() {
+            tree.skip_field("appeals")?;
+        } else {
+            tree.serialize_field("appeals", &self.appeals)?;
+        }
+
+        tree.serialize_field("leaves", &StoredLeaves(self))?;
+        tree.serialize_field("logged", &self.logged)?;
+        tree.end()
+    }
+}
+
+struct StoredUsers<'a>(&'a UserTree);
+
+impl Serialize for StoredUsers<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let tree = self.0;
+        serializer.collect_map(
+            tree.ids
+                .handles()
+                .map(|handle| (tree.ids.resolve(handle), tree.user(handle))),
+        )
+    }
+}
+
+struct StoredLeaves<'a>(&'a UserTree);
+
+impl Serialize for StoredLeaves<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let tree = self.0;
+        serializer.collect_seq(tree.ids.handles().map(|handle| tree.ids.resolve(handle)))
+    }
+}
+
+impl UserTree {
+    /// Creates a user tree for a certificate with an assigned admin.
+    pub fn new(certificate: &str, admin_key: PublicKey) -> UserTree {
+        let mut tree = UserTree {
+            certificate: certificate.to_owned(),
+            ids: Interner::default(),
+            nodes: Vec::new(),
+            redeemed_invitations: BTreeSet::new(),
+            removed: BTreeMap::new(),
+            policy: Policy::default(),
+            audit: Vec::new(),
+            appeals: Vec::new(),
+            commitment: MerkleTree::default(),
+            logged: 0,
+            time: 0,
+            statistics: Counters::default(),
+            journal: None,
+        };
+
+        // Assign an admin
+        let admin = tree.ids.intern(ADMIN_ID);
+        tree.nodes
+            .push(Node::new(admin, Role::CoAdmin, Limits::NONE, admin_key));
+        tree.commitment.push(tree.leaf(admin));
+        tree.reindex(admin);
+        tree.statistics.add(1);
+
+        tree
+    }
+
+    pub fn certificate(&self) -> &str {
+        &self.certificate
+    }
+
+    /// Length of the event log once the tree's last event was appended, zero
+    /// for trees stored before trees recorded it.
+    pub fn logged(&self) -> u64 {
+        self.logged
+    }
+
+    pub fn set_logged(&mut self, logged: u64) {
+        self.logged = logged;
+    }
+
+    /// Runs a change on the tree that either succeeds or, failing or
+    /// panicking, leaves the tree as it was. Undoing restores only what the
+    /// change touched, so the tree is never copied.
+    pub(crate) fn atomically<T>(&mut self, change: impl FnOnce(&mut UserTree) -> Result<T>) -> Result<T> {
+        let outer = self.journal.replace(Journal {
+            undo: Vec::new(),
+            time: self.time,
+            logged: self.logged,
+            statistics: self.statistics.clone(),
+        });
+        let changed = panic::catch_unwind(AssertUnwindSafe(|| change(self)));
+        let journal = mem::replace(&mut self.journal, outer).expect("changes keep their journal");
+
+        match changed {
+            Ok(Ok(value)) => {
+                // An enclosing change undoes this one too
+                if let Some(outer) = self.journal.as_mut() {
+                    outer.undo.extend(journal.undo);
+                }
+
+                Ok(value)
+            }
+            Ok(Err(error)) => {
+                self.undo(journal);
+                Err(error)
+            }
+            Err(panic) => {
+                self.undo(journal);
+                panic::resume_unwind(panic)
+            }
+        }
+    }
+
+    pub fn contains(&self, user_id: &str) -> bool {
+        self.ids.get(user_id).is_some()
+    }
+
+    pub fn get(&self, user_id: &str) -> Option<User> {
+        self.ids.get(user_id).map(|handle| self.user(handle))
+    }
+
+    pub fn users(&self) -> impl Iterator<Item = User> + '_ {
+        self.ids.handles().map(|handle| self.user(handle))
+    }
+
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    /// Replaces the tree's policy, recounting reports if their propagation
+    /// changed. Counts kept from before reports were recorded are dropped.
+    pub fn set_policy(&mut self, policy: Policy) {
+        let recount = policy.reports.propagation != self.policy.reports.propagation;
+        let previous = mem::replace(&mut self.policy, policy);
+        self.record(Undo::Policy(Box::new(previous)));
+
+        if recount {
+            self.recount_reports();
+        }
+    }
+
+    /// Ids of the users removed from the tree and not added again.
+    pub fn removed_users(&self) -> impl Iterator<Item = &str> {
+        self.removed.keys().map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn statistics(&self) -> Statistics {
+        self.statistics.snapshot()
+    }
+
+    /// Sets the Unix time at which limits are evaluated, so that replayed
+    /// operations see the time they were first applied at.
+    pub fn set_time(&mut self, now: u64) {
+        self.time = now;
+    }
+
+    /// Merkle root committing to the parent, children, reports and block
+    /// state of every user.
+    pub fn root(&self) -> Digest {
+        self.commitment.root()
+    }
+
+    /// Proof that the user's current record is committed to by the root.
+    pub fn inclusion_proof(&self, user_id: &str) -> Result<InclusionProof> {
+        let handle = self.handle(user_id)?;
+
+        Ok(InclusionProof {
+            certificate: self.certificate.clone(),
+            user: CommittedUser::new(&self.user(handle)),
+            path: self.commitment.proof(handle.index()),
+        })
+    }
+
+    /// Checks that the actor of an event signed it with its current nonce.
+    pub fn verify(&self, event: &Event, authorization: Option<&Authorization>) -> Result<()> {
+        let actor = match event.actor() {
+            Some(actor) => actor,
+            None => return Ok(()),
+        };
+
+        // A redeeming user signs with the key it joins with
+        let (public_key, nonce) = match (event, self.ids.get(actor)) {
+            (Event::RedeemInvitation { public_key, .. }, _) => (*public_key, 0),
+            (_, Some(handle)) => (self.node(handle).public_key, self.node(handle).nonce),
+            (_, None) => return Err(self.user_not_found(actor).context("actor", actor)),
+        };
+
+        let authorization = match authorization {
+            Some(authorization) => authorization,
+            None => {
+                return Err(Error::InvalidSignature {
+                    user_id: actor.to_owned(),
+                })
+            }
+        };
+
+        if authorization.nonce != nonce {
+            return Err(Error::InvalidNonce {
+                user_id: actor.to_owned(),
+                expected: nonce,
+                found: authorization.nonce,
+            });
+        }
+
+        if !public_key.verify(&event.signing_bytes(authorization.nonce), &authorization.signature) {
+            return Err(Error::InvalidSignature {
+                user_id: actor.to_owned(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Checks the approvals of an event, signed over the message its actor
+    /// signed, returning the ids of the approving users.
+    pub fn verify_approvals<'a>(
+        &self,
+        event: &Event,
+        authorization: Option<&Authorization>,
+        approvals: &'a [Approval],
+    ) -> Result<Vec<&'a str>> {
+        let nonce = authorization.map_or(0, |authorization| authorization.nonce);
+        let message = event.signing_bytes(nonce);
+
+        approvals
+            .iter()
+            .map(|approval| {
+                let approver = &approval.user_id;
+                let handle = self
+                    .handle(approver)
+                    .map_err(|error| error.context("approver", approver))?;
+
+                if !self.node(handle).public_key.verify(&message, &approval.signature) {
+                    return Err(Error::InvalidSignature {
+                        user_id: approver.clone(),
+                    });
+                }
+
+                Ok(approver.as_str())
+            })
+            .collect()
+    }
+
+    /// Whether the user is a descendant of the ancestor, both existing.
+    pub fn is_descendant(&self, user_id: &str, ancestor: &str) -> bool {
+        match (self.ids.get(user_id), self.ids.get(ancestor)) {
+            (Some(user), Some(ancestor)) => self.is_ancestor(ancestor, user),
+            _ => false,
+        }
+    }
+
+    /// Consumes the actor's nonce once its signed operation was applied.
+    pub fn advance_nonce(&mut self, user_id: &str) {
+        match self.ids.get(user_id) {
+            Some(handle) => {
+                let nonce = self.node(handle).nonce;
+                self.record(Undo::Nonce(handle, nonce));
+                self.raw_node_mut(handle).nonce = nonce + 1;
+            }
+            None => {
+                // A user that removed itself
+                if let Some(nonce) = self.removed.get_mut(user_id) {
+                    let previous = *nonce;
+                    *nonce += 1;
+                    self.record(Undo::RemovedNonce(user_id.to_owned(), Some(previous)));
+                }
+            }
+        }
+    }
+
+    /// Records the signed event that placed the user under its parent.
+    pub fn set_grant(&mut self, user_id: &str, grant: SignedEvent) {
+        if let Some(handle) = self.ids.get(user_id) {
+            self.replace_grant(handle, Some(grant));
+        }
+    }
+
+    /// Returns what a user effectively may do in the user tree, nothing if
+    /// it or one of its ancestors is blocked or its delegation expired.
+    ///
+    /// A user holds at most the capabilities, and is bound by the limits, of
+    /// each of its ancestors. Takes constant time, as what every user inherits
+    /// is kept up to date as the tree changes, except for users whose invites
+    /// the rules suspended, which are checked against their children.
+    pub fn check_user_permission(&self, user_id: &str) -> Result<Permission> {
+        self.check_user_permission_at(user_id, self.time)
+    }
+
+    /// Returns what a user effectively may do at the Unix time now, for
+    /// readers sharing the tree that can not set its time.
+    pub fn check_user_permission_at(&self, user_id: &str, now: u64) -> Result<Permission> {
+        let handle = self.handle(user_id)?;
+        let ancestry = self.node(handle).ancestry;
+
+        self.statistics.read(1);
+
+        if ancestry.blocked > 0 || now < ancestry.blocked_until || ancestry.limits.is_expired(now) {
+            return Ok(Permission::DENIED);
+        }
+
+        Ok(Permission {
+            capabilities: ancestry.capabilities.difference(self.suspended_at(handle, now)),
+            limits: ancestry.limits,
+        })
+    }
+
+    /// Capabilities the rules suspend for a user at the Unix time now.
+    pub fn user_suspended_at(&self, user_id: &str, now: u64) -> Result<Capabilities> {
+        Ok(self.suspended_at(self.handle(user_id)?, now))
+    }
+
+    /// Computes what [`UserTree::check_user_permission`] returns by walking
+    /// every ancestor up to the admin instead of using the index, to cross
+    /// check the index.
+    pub fn walk_user_permission(&self, user_id: &str) -> Result<Permission> {
+        let mut current = self.handle(user_id)?;
+        let mut capabilities = Capabilities::all().difference(self.suspended_at(current, self.time));
+        let mut limits = Limits::NONE;
+
+        loop {
+            let node = &self.nodes[current.index()];
+            self.statistics.read(1);
+
+            if node.is_blocked_at(self.time) {
+                return Ok(Permission::DENIED);
+            }
+
+            capabilities = capabilities.intersection(node.role.capabilities());
+            limits = limits.combine(node.limits);
+
+            if node.parent == current {
+                break;
+            }
+
+            current = node.parent;
+        }
+
+        if limits.is_expired(self.time) {
+            return Ok(Permission::DENIED);
+        }
+
+        Ok(Permission { capabilities, limits })
+    }
+
+    /// Limits binding a user from itself and its ancestors, whether it has
+    /// permission or not.
+    pub fn inherited_limits(&self, user_id: &str) -> Result<Limits> {
+        Ok(self.node(self.handle(user_id)?).ancestry.limits)
+    }
+
+    /// Adds a user to the user tree as a child of parent, granted a role
+    /// within the parent's own capabilities and limited further by limits.
+    pub fn add_user(
+        &mut self,
+        user_id: &str,
+        parent: &str,
+        role: Role,
+        limits: Limits,
+        public_key: PublicKey,
+    ) -> Result<()> {
+        if user_id == parent {
+            return Err(Error::SelfParent {
+                user_id: user_id.to_owned(),
+            });
+        }
+
+        let permission = self.require_capability(parent, "parent", Capability::Invite)?;
+
+        if let Some(capability) = role.capabilities().difference(permission.capabilities).iter().next() {
+            return Err(Error::MissingCapability {
+                user_id: parent.to_owned(),
+                capability,
+            }
+            .context("parent", parent));
+        }
+
+        self.require_child_slot(parent, &permission)?;
+
+        if self.contains(user_id) {
+            return Err(Error::UserAlreadyExists {
+                user_id: user_id.to_owned(),
+                certificate: self.certificate.clone(),
+            });
+        }
+
+        // Update Parent
+        let parent = self.handle(parent).map_err(|error| error.context("parent", parent))?;
+        self.statistics.read(1);
+        self.statistics.update(1);
+
+        // Add User, continuing with its nonce if it was removed before
+        let handle = self.ids.intern(user_id);
+        let mut node = Node::new(parent, role, limits, public_key);
+
+        if let Some(nonce) = self.removed.remove(user_id) {
+            node.nonce = nonce;
+            self.record(Undo::RemovedNonce(user_id.to_owned(), Some(nonce)));
+        }
+
+        self.nodes.push(node);
+        self.commitment.push(self.leaf(handle));
+        self.record(Undo::Added);
+        self.attach(handle, parent);
+        self.statistics.add(1);
+
+        self.rehash(parent);
+        self.reindex(handle);
+
+        Ok(())
+    }
+
+    /// Adds a user as a child of the parent that signed the invitation, once,
+    /// until the invitation expires.
+    pub fn redeem_invitation(
+        &mut self,
+        invitation: &SignedInvitation,
+        user_id: &str,
+        public_key: PublicKey,
+    ) -> Result<()> {
+        let signed = invitation;
+        let invitation = &signed.invitation;
+        let parent = &invitation.parent;
+
+        if invitation.certificate != self.certificate {
+            return Err(Error::InvalidInvitation {
+                reason: "issued for another certificate",
+            });
+        }
+
+        if invitation.expires_at < self.time {
+            return Err(Error::InvitationExpired {
+                expires_at: invitation.expires_at,
+            });
+        }
+
+        if invitation
+            .user_id
+            .as_deref()
+            .is_some_and(|intended| intended != user_id)
+        {
+            return Err(Error::InvalidInvitation {
+                reason: "issued for another user",
+            });
+        }
+
+        if self.redeemed_invitations.contains(&invitation.nonce) {
+            return Err(Error::InvitationRedeemed {
+                nonce: invitation.nonce.clone(),
+            });
+        }
+
+        let parent_handle = self.handle(parent).map_err(|error| error.context("parent", parent))?;
+
+        if !signed.verify(&self.node(parent_handle).public_key) {
+            return Err(Error::InvalidInvitation {
+                reason: "not signed by the parent",
+            });
+        }
+
+        self.add_user(user_id, parent, invitation.role, invitation.limits, public_key)?;
+        if self.redeemed_invitations.insert(invitation.nonce.clone()) {
+            self.record(Undo::Redeemed(invitation.nonce.clone()));
+        }
+
+        Ok(())
+    }
+
+    /// Records a report against a user by a reporter with permission. A
+    /// repeated report by the same reporter replaces the reason and text of
+    /// the earlier one but keeps its time, so that repeating a report does
+    /// not keep it from decaying, and only new reports increment the count of
+    /// the user and its ancestors.
+    pub fn report_user(
+        &mut self,
+        user_id: &str,
+        reporter: &str,
+        reason: ReportReason,
+        text: Option<&str>,
+    ) -> Result<()> {
+        let mut current = self.handle(user_id)?;
+        self.require_capability(reporter, "reporter", Capability::View)?;
+
+        self.statistics.read(1);
+        let time = self.time;
+        let node = self.node_mut(current);
+
+        if let Some(earlier) = node.report_log.iter_mut().find(|earlier| earlier.reporter == reporter) {
+            earlier.reason = reason;
+            earlier.text = text.map(str::to_owned);
+            self.statistics.update(1);
+            self.rehash(current);
+
+            return Ok(());
+        }
+
+        node.report_log.push(Report {
+            reporter: reporter.to_owned(),
+            reason,
+            text: text.map(str::to_owned),
+            time,
+        });
+
+        let propagation = self.policy.reports.propagation;
+        let mut distance = 0;
+
+        loop {
+            self.statistics.read(1);
+            self.statistics.update(1);
+
+            let (reports, parent) = (self.node(current).reports, self.node(current).parent);
+            self.record(Undo::Reports(current, reports));
+            self.raw_node_mut(current).reports = reports.saturating_add(1);
+
+            self.rehash(current);
+            distance += 1;
+
+            if parent == current || !propagation.counts(distance) {
+                break;
+            }
+
+            current = parent;
+        }
+
+        self.enforce_rules(self.handle(user_id)?);
+
+        Ok(())
+    }
+
+    /// The user's report score at the Unix time now, with every report
+    /// weighted by its age and, for the propagated score, by the distance of
+    /// the reported descendant.
+    pub fn report_score(&self, user_id: &str, now: u64) -> Result<ReportScore> {
+        let handle = self.handle(user_id)?;
+        let policy = self.policy.reports;
+
+        let score = |handle: Handle| -> f64 {
+            let reports = self.node(handle).report_log.iter();
+            reports.fold(0.0, |score, report| {
+                score + policy.age_weight(now.saturating_sub(report.time))
+            })
+        };
+
+        let direct = score(handle);
+        let mut propagated = direct;
+        let mut level = self.node(handle).children.clone();
+        let mut distance = 1;
+
+        while !level.is_empty() {
+            let weight = policy.propagation.weight(distance);
+
+            if weight <= 0.0 {
+                break;
+            }
+
+            self.statistics.read(level.len() as u32);
+            propagated += weight * level.iter().fold(0.0, |sum, child| sum + score(*child));
+            level = level
+                .iter()
+                .flat_map(|child| self.node(*child).children.iter().copied())
+                .collect();
+            distance += 1;
+        }
+
+        Ok(ReportScore { direct, propagated })
+    }
+
+    /// Counts every user's reports anew from the reports against it and the
+    /// descendants whose reports count for it under the propagation.
+    fn recount_reports(&mut self) {
+        let propagation = self.policy.reports.propagation;
+        let mut counts = vec![0u16; self.nodes.len()];
+
+        for handle in self.ids.handles() {
+            let reports = u16::try_from(self.node(handle).report_log.len()).unwrap_or(u16::MAX);
+
+            if reports == 0 {
+                continue;
+            }
+
+            let mut current = handle;
+            let mut distance = 0;
+
+            loop {
+                counts[current.index()] = counts[current.index()].saturating_add(reports);
+                let parent = self.node(current).parent;
+                distance += 1;
+
+                if parent == current || !propagation.counts(distance) {
+                    break;
+                }
+
+                current = parent;
+            }
+        }
+
+        for handle in self.ids.handles() {
+            if self.node(handle).reports != counts[handle.index()] {
+                self.node_mut(handle).reports = counts[handle.index()];
+                self.statistics.update(1);
+                self.rehash(handle);
+            }
+        }
+    }
+
+    /// Blocks a user from having permission in the user tree, for good or
+    /// for a number of seconds. A temporary block ends by itself and leaves
+    /// the user under its parent, and a blocked user can only be blocked again
+    /// to make its temporary block longer or permanent.
+    ///
+    /// Users can block themselves, moderators their children and
+    /// administrators any of their descendants.
+    pub fn block_user(
+        &mut self,
+        user_id: &str,
+        blocker: &str,
+        duration: Option<u64>,
+        reason: BlockReason,
+        text: Option<&str>,
+    ) -> Result<()> {
+        let handle = self.handle(user_id)?;
+        let node = self.node(handle);
+        self.statistics.read(1);
+
+        // A temporary block can be replaced by a longer or permanent one
+        let until = duration.map(|duration| self.time.saturating_add(duration));
+        let extended = match (node.blocked_until, until) {
+            (Some(_), None) => true,
+            (Some(current), Some(until)) => until > current,
+            (None, _) => false,
+        };
+
+        if node.is_blocked_at(self.time) && !extended {
+            return Err(Error::AlreadyBlocked {
+                user_id: user_id.to_owned(),
+            });
+        }
+
+        if user_id != blocker {
+            self.require_moderator(handle, user_id, blocker, "blocker")?;
+        }
+
+        let record = BlockRecord {
+            action: BlockAction::Block,
+            actor: Some(blocker.to_owned()),
+            reason,
+            text: text.map(str::to_owned),
+            time: self.time,
+            until,
+        };
+
+        self.block(handle, record);
+        self.enforce_rules(handle);
+
+        Ok(())
+    }
+
+    fn block(&mut self, handle: Handle, record: BlockRecord) {
+        let node = self.node_mut(handle);
+        node.blocked = true;
+        node.blocked_until = record.until;
+        node.block_history.push(record);
+        self.statistics.update(1);
+
+        self.rehash(handle);
+        self.reindex(handle);
+
+        // A pending appeal was against an earlier block
+        let user_id = self.ids.resolve(handle).to_owned();
+        self.lapse_appeals(&user_id);
+    }
+
+    /// Unblocks a user and moves it under the unblocker, keeping the role and
+    /// limits it inherited from its previous ancestors.
+    ///
+    /// Every user involved is looked up before the tree changes, so a failing
+    /// unblock leaves the tree as it was.
+    pub fn unblock_user(
+        &mut self,
+        user_id: &str,
+        unblocker: &str,
+        reason: BlockReason,
+        text: Option<&str>,
+    ) -> Result<()> {
+        // Check if unblocker may moderate and adopt another child
+        let permission = self.require_capability(unblocker, "unblocker", Capability::Moderate)?;
+        self.require_child_slot(unblocker, &permission)?;
+
+        // Check if user has already blocked
+        match self.check_user_permission(user_id) {
+            Ok(permission) => {
+                if permission.is_granted() {
+                    return Err(Error::AlreadyPermitted {
+                        user_id: user_id.to_owned(),
+                    });
+                }
+            }
+            Err(error) => return Err(error.context("user", user_id)),
+        }
+
+        let handle = self.handle(user_id)?;
+        let record = BlockRecord {
+            action: BlockAction::Unblock,
+            actor: Some(unblocker.to_owned()),
+            reason,
+            text: text.map(str::to_owned),
+            time: self.time,
+            until: None,
+        };
+        let unblocker = self.handle(unblocker)?;
+        let previous_parent = self.node(handle).parent;
+
+        // Unblock user and move it from its previous parent to the unblocker
+        self.inherit(handle, previous_parent);
+        self.detach(handle);
+        let node = self.node_mut(handle);
+        node.blocked = false;
+        node.blocked_until = None;
+        node.block_history.push(record);
+        self.attach(handle, unblocker);
+        self.statistics.read(3);
+        self.statistics.update(3);
+
+        for handle in [handle, previous_parent, unblocker] {
+            self.rehash(handle);
+        }
+
+        self.reindex(handle);
+        self.lapse_appeals(user_id);
+
+        // Both parents now have fewer blocked children
+        self.enforce_parent_rules(previous_parent);
+        self.enforce_parent_rules(unblocker);
+
+        Ok(())
+    }
+
+    /// Files a blocked user's appeal against its block. The appeal goes to
+    /// the ancestors of the blocker that may moderate, nearest first, and to
+    /// the policy's designated reviewer. Blocks by a rule, by the user itself
+    /// or by a removed user are appealed to the user's own ancestors.
+    ///
+    /// A user has at most one pending appeal.
+    pub fn file_appeal(&mut self, user_id: &str, statement: &str) -> Result<()> {
+        let handle = self.handle(user_id)?;
+        let node = self.node(handle);
+        self.statistics.read(1);
+
+        if !node.is_blocked_at(self.time) {
+            return Err(Error::NotBlocked {
+                user_id: user_id.to_owned(),
+            });
+        }
+
+        if let Some(appeal) = self
+            .appeals
+            .iter()
+            .find(|appeal| appeal.is_pending() && appeal.user_id == user_id)
+        {
+            return Err(Error::AppealPending {
+                user_id: user_id.to_owned(),
+                appeal: appeal.id,
+            });
+        }
+
+        let blocker = node
+            .block_history
+            .iter()
+            .rev()
+            .find(|record| record.action == BlockAction::Block);
+        let blocker = blocker.and_then(|record| record.actor.clone());
+
+        let mut current = match blocker.as_deref().and_then(|blocker| self.ids.get(blocker)) {
+            Some(blocker) if blocker != handle => blocker,
+            _ => handle,
+        };
+
+        let mut reviewers = Vec::new();
+
+        while self.node(current).parent != current {
+            current = self.node(current).parent;
+            let reviewer = self.ids.resolve(current);
+
+            if self
+                .check_user_permission(reviewer)
+                .is_ok_and(|permission| permission.capabilities.contains(Capability::Moderate))
+            {
+                reviewers.push(reviewer.to_owned());
+            }
+        }
+
+        // Blocks by the admin have no one above to go to
+        if reviewers.is_empty() {
+            reviewers.push(ADMIN_ID.to_owned());
+        }
+
+        let policy = &self.policy.appeals;
+        let designated = policy
+            .reviewer
+            .clone()
+            .filter(|reviewer| self.contains(reviewer) && reviewer != user_id);
+
+        if let Some(designated) = designated.as_ref().filter(|designated| !reviewers.contains(designated)) {
+            reviewers.push(designated.clone());
+        }
+
+        let appeal = Appeal {
+            id: self.appeals.len() as u64,
+            user_id: user_id.to_owned(),
+            statement: statement.to_owned(),
+            filed_at: self.time,
+            blocker,
+            quorum: policy.quorum.clamp(1, reviewers.len()),
+            reviewers,
+            designated,
+            state: AppealState::Pending,
+            votes: Vec::new(),
+            decided_at: None,
+        };
+
+        self.appeals.push(appeal);
+        self.record(Undo::Filed);
+        self.statistics.update(1);
+
+        Ok(())
+    }
+
+    /// Records a reviewer's vote on a pending appeal. Once the designated
+    /// reviewer or a quorum accepted it, the block is lifted and the user
+    /// moves under the deciding reviewer, unless it keeps its parent. Once
+    /// the designated reviewer rejected it or a quorum can no longer accept
+    /// it, the block stays.
+    ///
+    /// Reviewers must still be able to moderate, and a deciding reviewer
+    /// must have room for the user, before the tree changes.
+    pub fn review_appeal(&mut self, id: u64, reviewer: &str, accept: bool, keep_parent: bool) -> Result<()> {
+        let index = usize::try_from(id).ok().filter(|index| *index < self.appeals.len());
+
+        let index = index.ok_or_else(|| Error::AppealNotFound {
+            appeal: id,
+            certificate: self.certificate.clone(),
+        })?;
+
+        let mut appeal = self.appeals[index].clone();
+
+        if !appeal.is_pending() {
+            return Err(Error::AppealClosed { appeal: id });
+        }
+
+        if !appeal.reviewers.iter().any(|candidate| candidate == reviewer) {
+            return Err(Error::NotReviewer {
+                user_id: reviewer.to_owned(),
+                appeal: id,
+            });
+        }
+
+        if appeal.votes.iter().any(|vote| vote.reviewer == reviewer) {
+            return Err(Error::AlreadyReviewed {
+                user_id: reviewer.to_owned(),
+                appeal: id,
+            });
+        }
+
+        let permission = self.require_capability(reviewer, "reviewer", Capability::Moderate)?;
+        let handle = self.handle(&appeal.user_id)?;
+        let reviewer_handle = self.handle(reviewer)?;
+        self.statistics.read(1);
+
+        // A temporary block may have ended since the appeal was filed
+        if !self.node(handle).is_blocked_at(self.time) {
+            return Err(Error::NotBlocked {
+                user_id: appeal.user_id,
+            });
+        }
+
+        appeal.votes.push(Vote {
+            reviewer: reviewer.to_owned(),
+            accept,
+            time: self.time,
+        });
+
+        let decision = appeal.decision();
+
+        if decision == Some(true) && !keep_parent {
+            if reviewer_handle == handle || self.is_ancestor(handle, reviewer_handle) {
+                return Err(Error::ParentInSubtree {
+                    user_id: appeal.user_id,
+                    parent: reviewer.to_owned(),
+                });
+            }
+
+            self.require_child_slot(reviewer, &permission)?;
+        }
+
+        if let Some(accepted) = decision {
+            appeal.state = if accepted {
+                AppealState::Accepted
+            } else {
+                AppealState::Rejected
+            };
+            appeal.decided_at = Some(self.time);
+        }
+
+        let previous = mem::replace(&mut self.appeals[index], appeal);
+        self.record(Undo::Appeal(index, Box::new(previous)));
+        self.statistics.update(1);
+
+        if decision == Some(true) {
+            self.lift_block(handle, reviewer_handle, keep_parent, id);
+        }
+
+        Ok(())
+    }
+
+    /// Unblocks a user whose appeal was accepted, moving it under the
+    /// reviewer unless it keeps its parent, as [`UserTree::move_user`] does.
+    fn lift_block(&mut self, handle: Handle, reviewer: Handle, keep_parent: bool, appeal: u64) {
+        let record = BlockRecord {
+            action: BlockAction::Unblock,
+            actor: Some(self.ids.resolve(reviewer).to_owned()),
+            reason: BlockReason::Appeal,
+            text: Some(format!("appeal {appeal}")),
+            time: self.time,
+            until: None,
+        };
+        let previous_parent = self.node(handle).parent;
+
+        if !keep_parent {
+            self.inherit(handle, previous_parent);
+            self.detach(handle);
+            self.attach(handle, reviewer);
+            self.replace_grant(handle, None);
+        }
+
+        let node = self.node_mut(handle);
+        node.blocked = false;
+        node.blocked_until = None;
+        node.block_history.push(record);
+        let parent = node.parent;
+        self.statistics.update(3);
+
+        for handle in [handle, previous_parent, parent] {
+            self.rehash(handle);
+        }
+
+        self.reindex(handle);
+
+        // Both parents now have fewer blocked children
+        self.enforce_parent_rules(previous_parent);
+
+        if parent != previous_parent {
+            self.enforce_parent_rules(parent);
+        }
+    }
+
+    /// Lapses the user's pending appeal once its block ended or was replaced.
+    fn lapse_appeals(&mut self, user_id: &str) {
+        let time = self.time;
+
+        for index in 0..self.appeals.len() {
+            let appeal = &self.appeals[index];
+
+            if !appeal.is_pending() || appeal.user_id != user_id {
+                continue;
+            }
+
+            let lapsed = Appeal {
+                state: AppealState::Lapsed,
+                decided_at: Some(time),
+                ..appeal.clone()
+            };
+            let previous = mem::replace(&mut self.appeals[index], lapsed);
+            self.record(Undo::Appeal(index, Box::new(previous)));
+            self.statistics.update(1);
+        }
+    }
+
+    /// Every appeal filed in the tree, oldest first.
+    pub fn appeals(&self) -> &[Appeal] {
+        &self.appeals
+    }
+
+    pub fn appeal(&self, id: u64) -> Result<&Appeal> {
+        let appeal = usize::try_from(id).ok().and_then(|index| self.appeals.get(index));
+
+        appeal.ok_or_else(|| Error::AppealNotFound {
+            appeal: id,
+            certificate: self.certificate.clone(),
+        })
+    }
+
+    /// Applies the policy's rules after a report against or a block of the
+    /// user, first to the user and then to its parent, recording every action
+    /// taken in the audit trail.
+    fn enforce_rules(&mut self, handle: Handle) {
+        for rule in self.policy.rules.clone() {
+            if let Rule::BlockOnReports { reports } = rule {
+                let node = self.node(handle);
+
+                // The admin is never blocked
+                if !node.is_blocked_at(self.time) && node.parent != handle && node.report_log.len() >= reports {
+                    let record = BlockRecord {
+                        action: BlockAction::Block,
+                        actor: None,
+                        reason: BlockReason::Rule,
+                        text: Some(rule.to_string()),
+                        time: self.time,
+                        until: None,
+                    };
+
+                    self.block(handle, record);
+                    self.audit(
+                        rule,
+                        AutomaticAction::Block {
+                            user_id: self.ids.resolve(handle).to_owned(),
+                        },
+                    );
+                }
+            }
+        }
+
+        self.enforce_parent_rules(self.node(handle).parent);
+    }
+
+    /// Applies the policy's rules on blocked children to a parent,
+    /// suspending its invite capability while any of them is exceeded and
+    /// restoring it once none is, in the name of the rule that suspended it.
+    fn enforce_parent_rules(&mut self, parent: Handle) {
+        let suspended = self.node(parent).suspended.contains(Capability::Invite);
+        let exceeded = self.exceeded_parent_rule(parent, self.time);
+        let user_id = self.ids.resolve(parent).to_owned();
+
+        match exceeded {
+            Some(rule) if !suspended => {
+                let node = self.node_mut(parent);
+                node.suspended.insert(Capability::Invite);
+                node.suspended_by = Some(rule);
+                self.audit(rule, AutomaticAction::SuspendInvites { user_id });
+            }
+            None if suspended => {
+                let node = self.node_mut(parent);
+                node.suspended.remove(Capability::Invite);
+
+                // Suspensions stored before their rule was kept go to the
+                // policy's first rule on blocked children
+                let rule = node.suspended_by.take().or_else(|| {
+                    self.policy
+                        .rules
+                        .iter()
+                        .copied()
+                        .find(|rule| matches!(rule, Rule::SuspendInvitesOnBlockedChildren { .. }))
+                });
+
+                if let Some(rule) = rule {
+                    self.audit(rule, AutomaticAction::RestoreInvites { user_id });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The first of the policy's rules on blocked children that a parent
+    /// exceeds at the Unix time now.
+    fn exceeded_parent_rule(&self, parent: Handle, now: u64) -> Option<Rule> {
+        let node = self.node(parent);
+        let children = node.children.len();
+        let blocked = node
+            .children
+            .iter()
+            .filter(|child| self.node(**child).is_blocked_at(now))
+            .count();
+
+        self.statistics.read(children as u32 + 1);
+
+        self.policy.rules.iter().copied().find(|rule| match *rule {
+            Rule::SuspendInvitesOnBlockedChildren { percent, min_children } => {
+                children >= min_children && blocked * 100 > usize::from(percent) * children
+            }
+            _ => false,
+        })
+    }
+
+    /// Capabilities the rules suspend for a user at the Unix time now. The
+    /// invite capability comes back as soon as temporary blocks of the user's
+    /// children ended and no rule is exceeded any more, before the audit
+    /// trail records it when the tree next applies its rules to the user.
+    fn suspended_at(&self, handle: Handle, now: u64) -> Capabilities {
+        let mut suspended = self.node(handle).suspended;
+
+        if suspended.contains(Capability::Invite) && self.exceeded_parent_rule(handle, now).is_none() {
+            suspended.remove(Capability::Invite);
+        }
+
+        suspended
+    }
+
+    fn audit(&mut self, rule: Rule, action: AutomaticAction) {
+        self.statistics.update(1);
+        self.audit.push(AuditEntry {
+            time: self.time,
+            rule,
+            action,
+        });
+        self.record(Undo::Audited);
+    }
+
+    /// Actions the policy's rules took by themselves, oldest first.
+    pub fn audit_trail(&self) -> &[AuditEntry] {
+        &self.audit
+    }
+
+    /// Removes a user from the user tree, handling its children as orphans
+    /// says. The admin can not be removed.
+    ///
+    /// Users can remove themselves, moderators their children and
+    /// administrators any of their descendants. Children moved to a new
+    /// parent need its consent, by it or one of its ancestors signing or
+    /// approving the removal, and keep the role and limits they inherited
+    /// from the removed user. They lose their signed grant, as it names the
+    /// removed user, until the removal is recorded as their grant.
+    pub fn remove_user(&mut self, user_id: &str, remover: &str, orphans: &Orphans, approvers: &[&str]) -> Result<()> {
+        let handle = self.handle(user_id)?;
+        let parent = self.node(handle).parent;
+        self.statistics.read(1);
+
+        if parent == handle {
+            return Err(Error::AdminNotRemovable {
+                user_id: user_id.to_owned(),
+            });
+        }
+
+        // Removing itself must not free a blocked user's children
+        if user_id == remover {
+            self.require_capability(remover, "remover", Capability::View)?;
+        } else {
+            self.require_moderator(handle, user_id, remover, "remover")?;
+        }
+
+        let adopter = match orphans {
+            Orphans::Cascade => None,
+            Orphans::ReattachToParent => Some(parent),
+            Orphans::Adopt { adopter } => {
+                let adopter_handle = self
+                    .handle(adopter)
+                    .map_err(|error| error.context("adopter", adopter))?;
+
+                if adopter_handle == handle || self.is_ancestor(handle, adopter_handle) {
+                    return Err(Error::ParentInSubtree {
+                        user_id: user_id.to_owned(),
+                        parent: adopter.clone(),
+                    });
+                }
+
+                Some(adopter_handle)
+            }
+        };
+
+        let children = self.node(handle).children.clone();
+
+        if let Some(adopter) = adopter.filter(|_| !children.is_empty()) {
+            let consented = [remover].iter().chain(approvers).any(|approver| {
+                self.ids
+                    .get(approver)
+                    .is_some_and(|approver| approver == adopter || self.is_ancestor(approver, adopter))
+            });
+
+            if !consented {
+                return Err(Error::MissingApproval {
+                    user_id: self.ids.resolve(adopter).to_owned(),
+                });
+            }
+
+            // The adopter loses the removed user as a child if it was its parent
+            let kept = self.node(adopter).children.len() - usize::from(adopter == parent);
+            self.require_adoption(adopter, kept + children.len())?;
+        }
+
+        if adopter.is_some() {
+            for child in children.iter().copied() {
+                self.inherit(child, handle);
+            }
+        }
+
+        // Remove User from its parent
+        self.detach(handle);
+        self.statistics.update(1);
+        self.rehash(parent);
+
+        let removed = match adopter {
+            Some(adopter) => {
+                // Move Children
+                for child in children.iter().copied() {
+                    self.attach(child, adopter);
+                    self.replace_grant(child, None);
+                    self.statistics.update(1);
+                }
+
+                self.node_mut(handle).children.clear();
+                self.rehash(adopter);
+
+                for child in children.iter().copied() {
+                    self.rehash(child);
+                    self.reindex(child);
+                }
+
+                vec![handle]
+            }
+            None => self.subtree(handle),
+        };
+
+        self.remove_nodes(removed);
+
+        Ok(())
+    }
+
+    /// Moves a user and its whole subtree under a new parent, which must sign
+    /// or approve the move, with the further consent the tree's move policy
+    /// asks for.
+    ///
+    /// Users can move themselves, moderators their children and
+    /// administrators any of their descendants. The user keeps the role and
+    /// limits it inherited from its old ancestors, so that a move never
+    /// loosens what its subtree may do.
+    pub fn move_user(&mut self, user_id: &str, new_parent: &str, mover: &str, approvers: &[&str]) -> Result<()> {
+        let handle = self.handle(user_id)?;
+        let parent = self
+            .handle(new_parent)
+            .map_err(|error| error.context("new parent", new_parent))?;
+        let old_parent = self.node(handle).parent;
+        self.statistics.read(2);
+
+        // Also keeps the admin in place, as every other user is its descendant
+        if parent == handle || self.is_ancestor(handle, parent) {
+            return Err(Error::ParentInSubtree {
+                user_id: user_id.to_owned(),
+                parent: new_parent.to_owned(),
+            });
+        }
+
+        // Moving itself must not let a user escape a blocked ancestor
+        if user_id == mover {
+            self.require_capability(mover, "mover", Capability::View)?;
+        } else {
+            self.require_moderator(handle, user_id, mover, "mover")?;
+        }
+
+        let policy = self.policy.moves;
+        let approved = |approver: &str| approver == mover || approvers.contains(&approver);
+
+        if !approved(new_parent) {
+            return Err(Error::MissingApproval {
+                user_id: new_parent.to_owned(),
+            });
+        }
+
+        let old_parent_id = self.ids.resolve(old_parent);
+
+        if policy.old_parent_approval && !approved(old_parent_id) {
+            return Err(Error::MissingApproval {
+                user_id: old_parent_id.to_owned(),
+            });
+        }
+
+        if policy.user_approval && !approved(user_id) {
+            return Err(Error::MissingApproval {
+                user_id: user_id.to_owned(),
+            });
+        }
+
+        if old_parent == parent {
+            return Ok(());
+        }
+
+        if policy.new_parent_permission {
+            let permission = self.require_capability(new_parent, "new parent", Capability::Invite)?;
+            self.require_child_slot(new_parent, &permission)?;
+        }
+
+        // Move User from its old parent to the new one
+        self.inherit(handle, old_parent);
+        self.detach(handle);
+        self.attach(handle, parent);
+        self.replace_grant(handle, None);
+        self.statistics.update(3);
+
+        for handle in [handle, old_parent, parent] {
+            self.rehash(handle);
+        }
+
+        self.reindex(handle);
+
+        Ok(())
+    }
+
+    /// Folds the role cap and limits the user inherits from the ancestor into
+    /// its own, before it moves out from under that ancestor.
+    fn inherit(&mut self, handle: Handle, ancestor: Handle) {
+        let inherited = self.node(ancestor).ancestry;
+        let node = self.raw_node_mut(handle);
+        let previous = Undo::Rights(handle, node.role, node.limits);
+        node.role = node.role.min(Role::within(inherited.capabilities));
+        node.limits = node.limits.combine(inherited.limits);
+        self.record(previous);
+    }
+
+    /// Fails unless the actor is the user's parent holding the moderate
+    /// capability or a strict ancestor holding the administer capability.
+    fn require_moderator(&self, handle: Handle, user_id: &str, actor: &str, role: &'static str) -> Result<()> {
+        let capability = match self.ids.get(actor) {
+            Some(actor) if self.is_child(handle, actor) => Capability::Moderate,
+            Some(actor) if self.is_ancestor(actor, handle) => Capability::Administer,
+            _ => {
+                // Only user's ancestors or themselves can moderate the user.
+                return Err(Error::NotAuthorized {
+                    actor: actor.to_owned(),
+                    user_id: user_id.to_owned(),
+                });
+            }
+        };
+
+        self.require_capability(actor, role, capability).map(|_| ())
+    }
+
+    /// Fails unless the adopter may have children and as many as it would
+    /// have after adopting.
+    fn require_adoption(&self, adopter: Handle, children: usize) -> Result<()> {
+        let adopter_id = self.ids.resolve(adopter);
+        let permission = self.require_capability(adopter_id, "adopter", Capability::Invite)?;
+
+        match permission.limits.max_children {
+            Some(max_children) if children > max_children as usize => Err(Error::ChildLimitReached {
+                user_id: adopter_id.to_owned(),
+                max_children,
+            }
+            .context("adopter", adopter_id)),
+            _ => Ok(()),
+        }
+    }
+
+    /// The handles of the user and all its descendants.
+    fn subtree(&self, handle: Handle) -> Vec<Handle> {
+        let mut subtree = vec![handle];
+        let mut next = 0;
+
+        while let Some(&handle) = subtree.get(next) {
+            subtree.extend(self.node(handle).children.iter().copied());
+            next += 1;
+        }
+
+        subtree
+    }
+
+    /// Drops detached nodes from the arena, remembering their nonces.
+    ///
+    /// Every node is swapped with the last one, so nodes are dropped from the
+    /// highest handle down: the last node is then never one still to drop.
+    fn remove_nodes(&mut self, mut handles: Vec<Handle>) {
+        handles.sort_unstable_by_key(|handle| std::cmp::Reverse(handle.index()));
+
+        for handle in handles {
+            let user_id = self.ids.resolve(handle).to_owned();
+            self.lapse_appeals(&user_id);
+
+            let leaf = self.commitment.leaf(handle.index());
+            let node = self.nodes.swap_remove(handle.index());
+            let previous = self.removed.insert(user_id.clone(), node.nonce);
+            self.record(Undo::RemovedNonce(user_id.clone(), previous));
+            self.commitment.swap_remove(handle.index());
+            let moved = self.ids.swap_remove(handle);
+            self.record(Undo::Removed(handle, Box::new(node), user_id, leaf));
+
+            // Point the users around the moved node to its new handle
+            if let Some(moved) = moved {
+                let (parent, slot) = (self.node(handle).parent, self.node(handle).slot);
+
+                if parent == moved {
+                    self.place(handle, handle, slot);
+                } else {
+                    self.record(Undo::Child(parent, slot, moved));
+                    self.raw_node_mut(parent).children[slot] = handle;
+                }
+
+                for child in self.node(handle).children.clone() {
+                    let slot = self.node(child).slot;
+                    self.place(child, handle, slot);
+                }
+            }
+        }
+    }
+
+    /// Fails unless the user has permission and the capability, attributing
+    /// errors to its role in the operation. Returns the user's permission.
+    fn require_capability(&self, user_id: &str, role: &'static str, capability: Capability) -> Result<Permission> {
+        match self.check_user_permission(user_id) {
+            Ok(permission) if permission.capabilities.contains(capability) => Ok(permission),
+            Ok(permission) if !permission.is_granted() => Err(Error::PermissionDenied {
+                user_id: user_id.to_owned(),
+                certificate: self.certificate.clone(),
+            }),
+            Ok(_) => Err(Error::MissingCapability {
+                user_id: user_id.to_owned(),
+                capability,
+            }
+            .context(role, user_id)),
+            Err(error) => Err(error.context(role, user_id)),
+        }
+    }
+
+    /// Fails if the user already has as many children as its limits allow.
+    fn require_child_slot(&self, user_id: &str, permission: &Permission) -> Result<()> {
+        let children = self
+            .ids
+            .get(user_id)
+            .map_or(0, |handle| self.node(handle).children.len());
+
+        match permission.limits.max_children {
+            Some(max_children) if !permission.limits.allows_child(children) => Err(Error::ChildLimitReached {
+                user_id: user_id.to_owned(),
+                max_children,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn handle(&self, user_id: &str) -> Result<Handle> {
+        self.ids.get(user_id).ok_or_else(|| self.user_not_found(user_id))
+    }
+
+    fn node(&self, handle: Handle) -> &Node {
+        &self.nodes[handle.index()]
+    }
+
+    /// Borrows a node to change, recording a copy of it if a change is in
+    /// progress.
+    fn node_mut(&mut self, handle: Handle) -> &mut Node {
+        if let Some(journal) = self.journal.as_mut() {
+            let node = self.nodes[handle.index()].clone();
+            journal.undo.push(Undo::Node(handle, Box::new(node)));
+        }
+
+        &mut self.nodes[handle.index()]
+    }
+
+    /// Borrows a node for a change its caller records with a finer undo than
+    /// a copy of the node.
+    fn raw_node_mut(&mut self, handle: Handle) -> &mut Node {
+        &mut self.nodes[handle.index()]
+    }
+
+    fn record(&mut self, undo: Undo) {
+        if let Some(journal) = self.journal.as_mut() {
+            journal.undo.push(undo);
+        }
+    }
+
+    /// Reverts the changes of a journal, newest first.
+    fn undo(&mut self, journal: Journal) {
+        for undo in journal.undo.into_iter().rev() {
+            match undo {
+                Undo::Node(handle, node) => *self.raw_node_mut(handle) = *node,
+                Undo::Ancestry(handle, ancestry) => self.raw_node_mut(handle).ancestry = ancestry,
+                Undo::Reports(handle, reports) => self.raw_node_mut(handle).reports = reports,
+                Undo::Nonce(handle, nonce) => self.raw_node_mut(handle).nonce = nonce,
+                Undo::Grant(handle, grant) => self.raw_node_mut(handle).grant = *grant,
+                Undo::Rights(handle, role, limits) => {
+                    let node = self.raw_node_mut(handle);
+                    node.role = role;
+                    node.limits = limits;
+                }
+                Undo::Placed(handle, parent, slot) => {
+                    let node = self.raw_node_mut(handle);
+                    node.parent = parent;
+                    node.slot = slot;
+                }
+                Undo::Attached(parent) => {
+                    self.raw_node_mut(parent).children.pop();
+                }
+                Undo::Detached(parent, slot, child) => {
+                    let children = &mut self.raw_node_mut(parent).children;
+                    children.push(child);
+                    let last = children.len() - 1;
+                    children.swap(slot, last);
+                }
+                Undo::Child(parent, slot, child) => self.raw_node_mut(parent).children[slot] = child,
+                Undo::Leaf(index, leaf) => self.commitment.update(index, leaf),
+                Undo::Added => {
+                    self.commitment.swap_remove(self.nodes.len() - 1);
+                    self.nodes.pop();
+                    self.ids.pop();
+                }
+                Undo::Removed(handle, node, user_id, leaf) => {
+                    match self.nodes.get_mut(handle.index()) {
+                        Some(slot) => {
+                            let moved = mem::replace(slot, *node);
+                            self.nodes.push(moved);
+                        }
+                        None => self.nodes.push(*node),
+                    }
+
+                    self.ids.restore(handle, &user_id);
+                    self.commitment.restore(handle.index(), leaf);
+                }
+                Undo::RemovedNonce(user_id, nonce) => match nonce {
+                    Some(nonce) => {
+                        self.removed.insert(user_id, nonce);
+                    }
+                    None => {
+                        self.removed.remove(&user_id);
+                    }
+                },
+                Undo::Redeemed(nonce) => {
+                    self.redeemed_invitations.remove(&nonce);
+                }
+                Undo::Policy(policy) => self.policy = *policy,
+                Undo::Audited => {
+                    self.audit.pop();
+                }
+                Undo::Filed => {
+                    self.appeals.pop();
+                }
+                Undo::Appeal(index, appeal) => self.appeals[index] = *appeal,
+            }
+        }
+
+        self.time = journal.time;
+        self.logged = journal.logged;
+        self.statistics = journal.statistics;
+    }
+
+    /// Materializes the user behind a handle with its string ids.
+    fn user(&self, handle: Handle) -> User {
+        let node = self.node(handle);
+
+        User {
+            id: self.ids.resolve(handle).to_owned(),
+            parent: self.ids.resolve(node.parent).to_owned(),
+            children: node
+                .children
+                .iter()
+                .map(|child| self.ids.resolve(*child).to_owned())
+                .collect(),
+            reports: node.reports,
+            report_log: node.report_log.clone(),
+            blocked: node.blocked,
+            blocked_until: node.blocked_until,
+            block_history: node.block_history.clone(),
+            role: node.role,
+            suspended: node.suspended,
+            suspended_by: node.suspended_by,
+            limits: node.limits,
+            public_key: node.public_key,
+            nonce: node.nonce,
+            grant: node.grant.clone(),
+        }
+    }
+
+    /// Checks in constant time if child is a child of parent.
+    fn is_child(&self, child: Handle, parent: Handle) -> bool {
+        child != parent && self.node(child).parent == parent
+    }
+
+    /// Checks if ancestor is a strict ancestor of the user.
+    fn is_ancestor(&self, ancestor: Handle, user: Handle) -> bool {
+        let mut current = user;
+
+        while self.node(current).parent != current {
+            current = self.node(current).parent;
+
+            if current == ancestor {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Appends a node to its new parent's children.
+    fn attach(&mut self, handle: Handle, parent: Handle) {
+        let slot = self.node(parent).children.len();
+        self.raw_node_mut(parent).children.push(handle);
+        self.record(Undo::Attached(parent));
+        self.place(handle, parent, slot);
+    }
+
+    /// Removes a node from its parent's children in constant time, moving the
+    /// last child into its place.
+    fn detach(&mut self, handle: Handle) {
+        let (parent, slot) = (self.node(handle).parent, self.node(handle).slot);
+        let children = &mut self.raw_node_mut(parent).children;
+
+        children.swap_remove(slot);
+        let moved = children.get(slot).copied();
+        self.record(Undo::Detached(parent, slot, handle));
+
+        if let Some(moved) = moved {
+            self.place(moved, parent, slot);
+        }
+    }
+
+    /// Sets a node's parent and its slot among the parent's children.
+    fn place(&mut self, handle: Handle, parent: Handle, slot: usize) {
+        let node = self.raw_node_mut(handle);
+        let previous = Undo::Placed(handle, node.parent, node.slot);
+        node.parent = parent;
+        node.slot = slot;
+        self.record(previous);
+    }
+
+    fn replace_grant(&mut self, handle: Handle, grant: Option<SignedEvent>) {
+        let previous = mem::replace(&mut self.raw_node_mut(handle).grant, grant);
+        self.record(Undo::Grant(handle, Box::new(previous)));
+    }
+
+    fn leaf(&self, handle: Handle) -> Digest {
+        CommittedUser::new(&self.user(handle)).leaf()
+    }
+
+    /// Updates the user's leaf, rehashing only its path to the root.
+    fn rehash(&mut self, handle: Handle) {
+        let leaf = self.leaf(handle);
+        self.record(Undo::Leaf(handle.index(), self.commitment.leaf(handle.index())));
+        self.commitment.update(handle.index(), leaf);
+    }
+
+    /// Recomputes what the node and its whole subtree inherit, after it was
+    /// added, blocked, unblocked or moved.
+    fn reindex(&mut self, handle: Handle) {
+        let mut stack = vec![handle];
+
+        while let Some(handle) = stack.pop() {
+            let node = self.node(handle);
+
+            let inherited = if node.parent == handle {
+                Ancestry::root()
+            } else {
+                self.node(node.parent).ancestry
+            };
+
+            let ancestry = inherited.child(node);
+            stack.extend(node.children.iter().copied());
+            self.record(Undo::Ancestry(handle, node.ancestry));
+            self.raw_node_mut(handle).ancestry = ancestry;
+        }
+    }
+
+    fn user_not_found(&self, user_id: &str) -> Error {
+        Error::UserNotFound {
+            user_id: user_id.to_owned(),
+            certificate: self.certificate.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{Fixture, CERTIFICATE};
+
+    fn move_event(fixture: &Fixture, user_id: &str, new_parent: &str, mover: &str) -> Event {
+        fixture.database.move_event(user_id, new_parent, mover, CERTIFICATE)
+    }
+
+    fn parent(fixture: &Fixture, user_id: &str) -> String {
+        fixture.database.user_info(user_id, CERTIFICATE).unwrap().user.parent
+    }
+
+    #[test]
+    fn moves_need_the_new_parents_consent() {
+        let fixture = Fixture::with_users(&[("alice", ADMIN_ID), ("bob", "alice"), ("carol", ADMIN_ID)]);
+
+        // Neither the user itself nor its parent can push it onto carol
+        for mover in ["bob", "alice"] {
+            let moved = fixture.submit(move_event(&fixture, "bob", "carol", mover));
+            assert!(matches!(moved, Err(Error::MissingApproval { user_id }) if user_id == "carol"));
+        }
+
+        fixture
+            .submit_approved(move_event(&fixture, "bob", "carol", "bob"), &["carol"])
+            .unwrap();
+        assert_eq!(parent(&fixture, "bob"), "carol");
+
+        // The new parent moving a user consents by signing
+        fixture.submit(move_event(&fixture, "bob", "admin", ADMIN_ID)).unwrap();
+        assert_eq!(parent(&fixture, "bob"), ADMIN_ID);
+    }
+
+    #[test]
+    fn moves_keep_the_limits_of_the_old_ancestors() {
+        let mut fixture = Fixture::with_users(&[("carol", ADMIN_ID)]);
+        let limits = Limits {
+            max_children: Some(1),
+            valid_until: Some(2_000_000_000),
+        };
+
+        fixture.add_limited("alice", ADMIN_ID, Role::Inviter, limits).unwrap();
+        fixture.add("bob", "alice", Role::Inviter).unwrap();
+
+        let before = fixture.database.check_user_permission("bob", CERTIFICATE).unwrap();
+        fixture
+            .submit_approved(move_event(&fixture, "bob", "carol", "bob"), &["carol"])
+            .unwrap();
+        let after = fixture.database.check_user_permission("bob", CERTIFICATE).unwrap();
+
+        assert_eq!(parent(&fixture, "bob"), "carol");
+        assert_eq!(after, before);
+        assert_eq!(after.capabilities, Role::Inviter.capabilities());
+    }
+
+    #[test]
+    fn unblocks_and_appeals_keep_the_limits_of_the_old_ancestors() {
+        let mut fixture = Fixture::with_users(&[("carol", ADMIN_ID)]);
+        let limits = Limits {
+            max_children: Some(1),
+            valid_until: Some(2_000_000_000),
+        };
+
+        fixture.add_limited("alice", ADMIN_ID, Role::Inviter, limits).unwrap();
+        fixture.add("bob", "alice", Role::Inviter).unwrap();
+        let before = fixture.database.check_user_permission("bob", CERTIFICATE).unwrap();
+
+        // The admin blocks bob and carol takes it in
+        fixture.block("bob", ADMIN_ID, None).unwrap();
+        fixture
+            .submit(
+                fixture
+                    .database
+                    .unblock_event("bob", "carol", BlockReason::default(), None, CERTIFICATE),
+            )
+            .unwrap();
+        assert_eq!(parent(&fixture, "bob"), "carol");
+        assert_eq!(
+            fixture.database.check_user_permission("bob", CERTIFICATE).unwrap(),
+            before
+        );
+
+        // carol blocks bob and the admin accepts its appeal
+        fixture.block("bob", "carol", None).unwrap();
+        fixture
+            .submit(Event::FileAppeal {
+                certificate: CERTIFICATE.to_owned(),
+                user_id: "bob".to_owned(),
+                statement: "sorry".to_owned(),
+            })
+            .unwrap();
+        fixture
+            .submit(fixture.database.review_event(0, ADMIN_ID, true, false, CERTIFICATE))
+            .unwrap();
+        assert_eq!(parent(&fixture, "bob"), ADMIN_ID);
+        assert_eq!(
+            fixture.database.check_user_permission("bob", CERTIFICATE).unwrap(),
+            before
+        );
+    }
+
+    #[test]
+    fn removals_keep_the_limits_of_the_removed_user() {
+        let mut fixture = Fixture::with_users(&[("carol", ADMIN_ID)]);
+        let limits = Limits {
+            max_children: Some(1),
+            valid_until: Some(2_000_000_000),
+        };
+
+        fixture.add_limited("alice", ADMIN_ID, Role::Inviter, limits).unwrap();
+        fixture.add("bob", "alice", Role::Inviter).unwrap();
+        let before = fixture.database.check_user_permission("bob", CERTIFICATE).unwrap();
+
+        let orphans = Orphans::Adopt {
+            adopter: "carol".to_owned(),
+        };
+        fixture
+            .submit(fixture.database.remove_event("alice", ADMIN_ID, orphans, CERTIFICATE))
+            .unwrap();
+
+        assert_eq!(parent(&fixture, "bob"), "carol");
+        assert_eq!(
+            fixture.database.check_user_permission("bob", CERTIFICATE).unwrap(),
+            before
+        );
+    }
+
+    #[test]
+    fn users_can_not_move_into_their_own_subtree() {
+        let fixture = Fixture::with_users(&[("alice", ADMIN_ID), ("bob", "alice")]);
+
+        let moved = fixture.submit_approved(move_event(&fixture, "alice", "bob", ADMIN_ID), &["bob"]);
+        assert!(matches!(moved, Err(Error::ParentInSubtree { .. })));
+    }
+
+    #[test]
+    fn temporary_blocks_end_by_themselves() {
+        let fixture = Fixture::with_users(&[("alice", ADMIN_ID), ("bob", "alice")]);
+
+        fixture.block("alice", ADMIN_ID, Some(60)).unwrap();
+        assert!(!fixture.permitted("alice"));
+        assert!(!fixture.permitted("bob"));
+
+        fixture.clock.advance(60);
+        assert!(fixture.permitted("alice"));
+        assert!(fixture.permitted("bob"));
+        assert_eq!(parent(&fixture, "alice"), ADMIN_ID);
+    }
+
+    #[test]
+    fn temporary_blocks_can_only_be_extended() {
+        let fixture = Fixture::with_users(&[("alice", ADMIN_ID)]);
+        fixture.block("alice", ADMIN_ID, Some(60)).unwrap();
+
+        let shortened = fixture.block("alice", ADMIN_ID, Some(30));
+        assert!(matches!(shortened, Err(Error::AlreadyBlocked { .. })));
+
+        fixture.block("alice", ADMIN_ID, Some(120)).unwrap();
+        fixture.clock.advance(90);
+        assert!(!fixture.permitted("alice"));
+
+        fixture.block("alice", ADMIN_ID, None).unwrap();
+        fixture.clock.advance(3600);
+        assert!(!fixture.permitted("alice"));
+
+        let temporary = fixture.block("alice", ADMIN_ID, Some(7200));
+        assert!(matches!(temporary, Err(Error::AlreadyBlocked { .. })));
+    }
+
+    #[test]
+    fn suspended_invites_come_back_when_temporary_blocks_end() {
+        let mut fixture = Fixture::with_users(&[("alice", ADMIN_ID), ("bob", "alice")]);
+        let rule = Rule::SuspendInvitesOnBlockedChildren {
+            percent: 50,
+            min_children: 1,
+        };
+        fixture
+            .submit(Event::SetPolicy {
+                certificate: CERTIFICATE.to_owned(),
+                policy: Policy {
+                    rules: vec![rule],
+                    ..Policy::default()
+                },
+            })
+            .unwrap();
+
+        fixture.block("bob", "alice", Some(60)).unwrap();
+        assert!(fixture.add("carol", "alice", Role::Member).is_err());
+
+        fixture.clock.advance(60);
+        let alice = fixture.database.check_user_permission("alice", CERTIFICATE).unwrap();
+        assert!(alice.capabilities.contains(Capability::Invite));
+        fixture.add("carol", "alice", Role::Member).unwrap();
+    }
+
+    #[test]
+    fn removals_keep_the_arena_consistent() {
+        let mut fixture = Fixture::with_users(&[
+            ("alice", ADMIN_ID),
+            ("bob", "alice"),
+            ("carol", "alice"),
+            ("dave", "bob"),
+            ("erin", "bob"),
+            ("frank", "carol"),
+            ("grace", "frank"),
+        ]);
+        let remove = |user_id: &str, remover: &str, orphans: Orphans| {
+            let event = fixture.database.remove_event(user_id, remover, orphans, CERTIFICATE);
+            fixture.submit(event).unwrap();
+        };
+
+        remove("carol", ADMIN_ID, Orphans::Cascade);
+        remove("bob", "alice", Orphans::ReattachToParent);
+        remove(
+            "alice",
+            ADMIN_ID,
+            Orphans::Adopt {
+                adopter: ADMIN_ID.to_owned(),
+            },
+        );
+        fixture.add("henry", "erin", Role::Member).unwrap();
+
+        fixture
+            .database
+            .read_tree(CERTIFICATE, |tree| {
+                let mut users: Vec<String> = tree.users().map(|user| user.id).collect();
+                users.sort();
+                assert_eq!(users, [ADMIN_ID, "dave", "erin", "henry"]);
+                assert_eq!(tree.get("henry").unwrap().parent, "erin");
+
+                // The stored form holds together and reloads to the same root
+                let json = serde_json::to_string(tree).unwrap();
+                let stored: StoredTree = serde_json::from_str(&json).unwrap();
+                assert_eq!(stored.check(), []);
+                let reloaded: UserTree = serde_json::from_str(&json).unwrap();
+                assert_eq!(reloaded.root(), tree.root());
+
+                for user_id in users.iter() {
+                    assert!(tree.inclusion_proof(user_id).unwrap().verify(&tree.root()));
+                    assert_eq!(
+                        tree.check_user_permission(user_id).unwrap(),
+                        tree.walk_user_permission(user_id).unwrap()
+                    );
+                }
+            })
+            .unwrap();
+    }
+
+    /// Whether the index gives every user the permission a walk up the tree
+    /// gives it.
+    fn indexed(fixture: &Fixture) -> bool {
+        fixture
+            .database
+            .read_tree(CERTIFICATE, |tree| {
+                tree.users().all(|user| {
+                    tree.check_user_permission(&user.id).unwrap() == tree.walk_user_permission(&user.id).unwrap()
+                })
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn the_index_follows_blocks_moves_and_expiries() {
+        let mut fixture = Fixture::with_users(&[("alice", ADMIN_ID), ("bob", "alice"), ("dave", ADMIN_ID)]);
+        fixture.add("carol", "bob", Role::Inviter).unwrap();
+        assert!(indexed(&fixture));
+
+        fixture.block("bob", "alice", None).unwrap();
+        assert!(!fixture.permitted("carol"));
+        assert!(indexed(&fixture));
+
+        fixture
+            .submit_approved(move_event(&fixture, "carol", "dave", ADMIN_ID), &["dave"])
+            .unwrap();
+        assert!(fixture.permitted("carol"));
+        assert!(indexed(&fixture));
+
+        fixture.block("dave", ADMIN_ID, Some(60)).unwrap();
+        assert!(!fixture.permitted("carol"));
+        assert!(indexed(&fixture));
+
+        fixture.clock.advance(60);
+        let event = fixture
+            .database
+            .unblock_event("bob", "alice", BlockReason::Resolved, None, CERTIFICATE);
+        fixture.submit(event).unwrap();
+        assert!(fixture.permitted("bob"));
+        assert!(fixture.permitted("carol"));
+        assert!(indexed(&fixture));
+    }
+}
@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+
+use crate::delegation::Limits;
+use crate::event::SignedEvent;
+use crate::history::BlockRecord;
+use crate::identity::PublicKey;
+use crate::moderation::Rule;
+use crate::report::Report;
+use crate::role::{Capabilities, Role};
+
+/// Id of the root user of every certificate tree.
+pub const ADMIN_ID: &str = "admin";
+
+/// Represents a user in the system.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct User {
+    pub id: String,
+    pub parent: String,
+    pub children: Vec<String>,
+    /// Number of distinct reports against the user and the descendants whose
+    /// reports weigh at least half for it under the tree's propagation.
+    pub reports: u16,
+    /// Reports against the user itself, one per reporter.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub report_log: Vec<Report>,
+    pub blocked: bool,
+    /// Unix time a temporary block ends at, none for a block for good.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blocked_until: Option<u64>,
+    /// Every block and unblock of the user, oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub block_history: Vec<BlockRecord>,
+    /// Capabilities the parent granted the user, at most its own.
+    #[serde(default = "Role::legacy")]
+    pub role: Role,
+    /// Capabilities of the role the tree's rules suspended, for the user
+    /// alone and not its descendants.
+    #[serde(default, skip_serializing_if = "Capabilities::is_empty")]
+    pub suspended: Capabilities,
+    /// The rule that suspended the user's invite capability.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suspended_by: Option<Rule>,
+    /// Limits the parent placed on the user's subtree.
+    #[serde(default, skip_serializing_if = "Limits::is_none")]
+    pub limits: Limits,
+    /// Key the user signs its operations with.
+    pub public_key: PublicKey,
+    /// Number of signed operations the user has performed so far.
+    pub nonce: u64,
+    /// The signed event that placed the user under its current parent, none
+    /// for the admin.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grant: Option<SignedEvent>,
+}
+
+impl User {
+    pub fn new(id: &str, parent: &str, role: Role, limits: Limits, public_key: PublicKey) -> User {
+        User {
+            id: id.to_owned(),
+            parent: parent.to_owned(),
+            children: Vec::new(),
+            reports: 0,
+            report_log: Vec::new(),
+            blocked: false,
+            blocked_until: None,
+            block_history: Vec::new(),
+            role,
+            suspended: Capabilities::NONE,
+            suspended_by: None,
+            limits,
+            public_key,
+            nonce: 0,
+            grant: None,
+        }
+    }
+
+    /// Whether the user is blocked at the Unix time now, which it is not once
+    /// a temporary block ended.
+    pub fn is_blocked_at(&self, now: u64) -> bool {
+        self.blocked && self.blocked_until.is_none_or(|until| now < until)
+    }
+
+    /// Only admin has identical user id and parent id
+    pub fn is_admin(&self) -> bool {
+        self.parent == self.id
+    }
+}